@@ -1,7 +1,7 @@
-use lib_dto::book::{BookInfo, BookList};
+use lib_dto::book::{BookImportReport, BookInfo, BookKey, BookList};
 
 use crate::context::app_context::ModelManager;
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 pub struct BookBmc;
 const INSERT_BOOK: &str = r#"
@@ -16,24 +16,83 @@ VALUES
 RETURNING id;
 "#;
 
+const INSERT_MISSING_BOOK: &str = r#"
+INSERT INTO book_info (title, author, isbn, description)
+VALUES ($1, $2, $3, $4)
+ON CONFLICT DO NOTHING;
+"#;
+
+/// Matched by isbn, a deleted book is restored. `created` is false for an updated row.
+const UPSERT_BOOK: &str = r#"
+INSERT INTO book_info (title, author, isbn, description)
+VALUES ($1, $2, $3, $4)
+ON CONFLICT (isbn) DO UPDATE
+SET title = EXCLUDED.title,
+    author = EXCLUDED.author,
+    description = EXCLUDED.description,
+    deleted_at = NULL,
+    updated_at = now()
+RETURNING (xmax = 0) AS created;
+"#;
+
+const UPDATE_BOOK: &str = r#"
+UPDATE book_info
+SET title = $2, author = $3, isbn = $4, description = $5, updated_at = now()
+WHERE id = $1 AND deleted_at IS NULL
+RETURNING *;
+"#;
+
+const DELETE_BOOK: &str = r#"
+UPDATE book_info
+SET deleted_at = now(), updated_at = now()
+WHERE id = $1 AND deleted_at IS NULL
+RETURNING id;
+"#;
+
 const SELECT_ALL: &str = r#"
-SELECT * FROM book_info;
+SELECT * FROM book_info WHERE deleted_at IS NULL ORDER BY id;
+"#;
+
+const SELECT_BY_ID: &str = r#"
+SELECT * FROM book_info WHERE id=$1 AND deleted_at IS NULL;
+"#;
+
+const SELECT_BY_ISBN: &str = r#"
+SELECT * FROM book_info WHERE isbn=$1 AND deleted_at IS NULL;
 "#;
 
 const SELECT_BY_TITLE: &str = r#"
-SELECT * FROM book_info WHERE title=$1;
+SELECT * FROM book_info WHERE title=$1 AND deleted_at IS NULL;
 "#;
 
 const SELECT_BY_DESCRIPTION: &str = r#"
-SELECT * FROM book_info WHERE description ILIKE $1;
+SELECT * FROM book_info WHERE description ILIKE $1 AND deleted_at IS NULL;
 "#;
 
 impl BookBmc {
+    /// Returns the id of the new book, a taken title or isbn is a `BookConflict`.
     pub async fn create(
         mm: &ModelManager,
         book: &BookInfo,
+    ) -> Result<i64> {
+        let id = sqlx::query_scalar(INSERT_BOOK)
+            .bind(&book.title)
+            .bind(&book.author)
+            .bind(&book.isbn)
+            .bind(&book.description)
+            .fetch_one(mm.pg_pool())
+            .await
+            .map_err(|e| conflict(e, book))?;
+
+        Ok(id)
+    }
+
+    /// Creates the book unless its title or isbn is taken.
+    pub async fn create_missing(
+        mm: &ModelManager,
+        book: &BookInfo,
     ) -> Result<()> {
-        sqlx::query(INSERT_BOOK)
+        sqlx::query(INSERT_MISSING_BOOK)
             .bind(&book.title)
             .bind(&book.author)
             .bind(&book.isbn)
@@ -44,6 +103,80 @@ impl BookBmc {
         Ok(())
     }
 
+    /// Creates or updates the books by isbn, all of them or none.
+    pub async fn import(
+        mm: &ModelManager,
+        books: &[BookInfo],
+    ) -> Result<BookImportReport> {
+        let mut tx = mm.pg_pool().begin().await?;
+        let mut created = 0;
+        for book in books {
+            let inserted: bool = sqlx::query_scalar(UPSERT_BOOK)
+                .bind(&book.title)
+                .bind(&book.author)
+                .bind(&book.isbn)
+                .bind(&book.description)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(|e| conflict(e, book))?;
+            if inserted {
+                created += 1;
+            }
+        }
+        tx.commit().await?;
+
+        Ok(BookImportReport::new(created, books.len() - created))
+    }
+
+    /// Replaces every field of the book `id`, a deleted book is not found.
+    pub async fn update(
+        mm: &ModelManager,
+        id: i64,
+        book: &BookInfo,
+    ) -> Result<BookInfo> {
+        let book_stored = sqlx::query_as(UPDATE_BOOK)
+            .bind(id)
+            .bind(&book.title)
+            .bind(&book.author)
+            .bind(&book.isbn)
+            .bind(&book.description)
+            .fetch_optional(mm.pg_pool())
+            .await
+            .map_err(|e| conflict(e, book))?;
+
+        book_stored.ok_or_else(|| Error::BookNotFound(format!("id {}", id)))
+    }
+
+    /// Hides the book from the catalog, orders and storage keep referencing it.
+    pub async fn delete(
+        mm: &ModelManager,
+        id: i64,
+    ) -> Result<()> {
+        let deleted: Option<i64> = sqlx::query_scalar(DELETE_BOOK)
+            .bind(id)
+            .fetch_optional(mm.pg_pool())
+            .await?;
+
+        deleted.map(|_| ()).ok_or_else(|| Error::BookNotFound(format!("id {}", id)))
+    }
+
+    pub async fn get(
+        mm: &ModelManager,
+        key: &BookKey,
+    ) -> Result<BookInfo> {
+        let book = match key {
+            BookKey::BookId(id) => sqlx::query_as(SELECT_BY_ID).bind(id),
+            BookKey::Isbn(isbn) => sqlx::query_as(SELECT_BY_ISBN).bind(isbn),
+        }
+            .fetch_optional(mm.pg_pool())
+            .await?;
+
+        book.ok_or_else(|| match key {
+            BookKey::BookId(id) => Error::BookNotFound(format!("id {}", id)),
+            BookKey::Isbn(isbn) => Error::BookNotFound(format!("isbn {}", isbn)),
+        })
+    }
+
     pub async fn get_all(
         mm: &ModelManager,
     ) -> Result<BookList> {
@@ -79,4 +212,16 @@ impl BookBmc {
 
         Ok(BookList::new(books))
     }
-}
\ No newline at end of file
+}
+
+/// A violated UNIQUE constraint of `book_info` is a conflict on its column.
+fn conflict(e: sqlx::Error, book: &BookInfo) -> Error {
+    if let sqlx::Error::Database(db_error) = &e {
+        match db_error.constraint() {
+            Some("book_info_title_key") => return Error::BookConflict { field: "title", value: book.title.clone() },
+            Some("book_info_isbn_key") => return Error::BookConflict { field: "isbn", value: book.isbn.clone() },
+            _ => {}
+        }
+    }
+    e.into()
+}
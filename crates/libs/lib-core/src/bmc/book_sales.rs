@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+
+use sqlx::{Postgres, Transaction};
+
+use lib_dto::book::BookSales;
+use lib_dto::order::OrderStored;
+
+use crate::context::app_context::ModelManager;
+use crate::error::Result;
+
+pub struct BookSalesBmc;
+
+const INSERT_PROJECTED: &str = r#"
+INSERT INTO projected_order (order_id) VALUES ($1)
+ON CONFLICT (order_id) DO NOTHING;
+"#;
+
+const ADD_SOLD: &str = r#"
+INSERT INTO book_sales (book_id, sold) VALUES ($1, $2)
+ON CONFLICT (book_id) DO UPDATE SET sold = book_sales.sold + EXCLUDED.sold, updated_at = now();
+"#;
+
+const SELECT_SALES: &str = r#"
+SELECT book_id, sold FROM book_sales ORDER BY book_id;
+"#;
+
+const CLEANUP_SALES: &str = r#"
+TRUNCATE book_sales, projected_order;
+"#;
+
+impl BookSalesBmc {
+    /// Counts the books of a delivered order, once per order, in the transaction of the consumer.
+    /// Returns false when the order was already counted.
+    pub async fn add_delivered_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        order: &OrderStored,
+    ) -> Result<bool> {
+        let inserted = sqlx::query(INSERT_PROJECTED)
+            .bind(order.order_id())
+            .execute(&mut **tx)
+            .await?
+            .rows_affected() == 1;
+        if !inserted {
+            return Ok(false);
+        }
+
+        // summed per book and applied in book order, like the stock updates
+        let mut sold: BTreeMap<i64, i64> = BTreeMap::new();
+        for item in order.content() {
+            *sold.entry(item.book_id()).or_default() += item.quantity();
+        }
+        for (book_id, quantity) in sold {
+            sqlx::query(ADD_SOLD)
+                .bind(book_id)
+                .bind(quantity)
+                .execute(&mut **tx)
+                .await?;
+        }
+
+        Ok(true)
+    }
+
+    pub async fn get_all(
+        mm: &ModelManager,
+    ) -> Result<Vec<BookSales>> {
+        let sales = sqlx::query_as(SELECT_SALES)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(sales)
+    }
+
+    pub async fn cleanup_sales(
+        mm: &ModelManager,
+    ) -> Result<()> {
+        sqlx::query(CLEANUP_SALES)
+            .execute(mm.pg_pool())
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use sqlx::{Postgres, Transaction};
+
+use crate::context::app_context::ModelManager;
+use crate::error::Result;
+
+pub struct ConsumerOffsetBmc;
+
+/// Moves past `$4` unless the group already did, the row stays locked until the transaction ends,
+/// so two consumers of a partition can't both process a message.
+const ADVANCE_OFFSET: &str = r#"
+INSERT INTO consumer_offset (group_id, topic, partition, next_offset)
+VALUES ($1, $2, $3, $4 + 1)
+ON CONFLICT (group_id, topic, partition) DO UPDATE
+SET next_offset = EXCLUDED.next_offset, updated_at = now()
+WHERE consumer_offset.next_offset <= $4;
+"#;
+
+const SELECT_OFFSET: &str = r#"
+SELECT next_offset FROM consumer_offset WHERE group_id = $1 AND topic = $2 AND partition = $3;
+"#;
+
+impl ConsumerOffsetBmc {
+    /// Stores the offset after `offset` in the transaction of the side effects of its message.
+    /// Returns false when the message was already processed, the transaction must not go on then.
+    pub async fn advance_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        group_id: &str,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> Result<bool> {
+        let advanced = sqlx::query(ADVANCE_OFFSET)
+            .bind(group_id)
+            .bind(topic)
+            .bind(partition)
+            .bind(offset)
+            .execute(&mut **tx)
+            .await?
+            .rows_affected() == 1;
+
+        Ok(advanced)
+    }
+
+    /// Offset of the next message the group processes, none before its first one.
+    pub async fn next_offset(
+        mm: &ModelManager,
+        group_id: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<Option<i64>> {
+        let next_offset = sqlx::query_scalar(SELECT_OFFSET)
+            .bind(group_id)
+            .bind(topic)
+            .bind(partition)
+            .fetch_optional(mm.pg_pool())
+            .await?;
+
+        Ok(next_offset)
+    }
+}
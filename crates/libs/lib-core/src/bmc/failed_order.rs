@@ -0,0 +1,67 @@
+use sqlx::{Postgres, Transaction};
+
+use lib_dto::order::FailedOrder;
+
+use crate::context::app_context::ModelManager;
+use crate::error::Result;
+
+pub struct FailedOrderBmc;
+
+const INSERT_FAILED_ORDER: &str = r#"
+INSERT INTO failed_order (order_id, stage, attempts, last_error)
+VALUES ($1, $2, $3, $4);
+"#;
+
+const SELECT_PENDING: &str = r#"
+SELECT * FROM failed_order WHERE requeued_at IS NULL ORDER BY id;
+"#;
+
+const MARK_REQUEUED: &str = r#"
+UPDATE failed_order
+SET requeued_at = now()
+WHERE order_id = $1 AND requeued_at IS NULL;
+"#;
+
+impl FailedOrderBmc {
+    pub async fn insert_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: i64,
+        stage: &str,
+        attempts: i32,
+        last_error: &str,
+    ) -> Result<()> {
+        sqlx::query(INSERT_FAILED_ORDER)
+            .bind(order_id)
+            .bind(stage)
+            .bind(attempts)
+            .bind(last_error)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Dead letters which were not requeued yet, oldest first.
+    pub async fn list_pending(
+        mm: &ModelManager,
+    ) -> Result<Vec<FailedOrder>> {
+        let failed_orders = sqlx::query_as(SELECT_PENDING)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(failed_orders)
+    }
+
+    /// Returns false when the order has no pending dead letter.
+    pub async fn mark_requeued_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: i64,
+    ) -> Result<bool> {
+        let result = sqlx::query(MARK_REQUEUED)
+            .bind(order_id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
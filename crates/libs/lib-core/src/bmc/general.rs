@@ -1,74 +1,196 @@
-use std::collections::HashMap;
-use std::ops::Add;
-use std::sync::Arc;
+use std::collections::BTreeMap;
 
 use sqlx::{Postgres, Transaction};
 use tracing::{debug, instrument};
 
-use lib_dto::order::{OrderItem, OrderStatus, OrderStored};
+use lib_dto::order::{OrderStatus, OrderStored};
 
+use crate::bmc::failed_order::FailedOrderBmc;
 use crate::bmc::order::OrderBmc;
+use crate::bmc::outbox::OutboxBmc;
 use crate::bmc::storage::{StorageBmc, UpdateType};
 use crate::context::app_context::ModelManager;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::task::kafka::order_event;
 
-const SET_TX_ISOLATION_LEVEL: &str = r#"
-SET TRANSACTION ISOLATION LEVEL
-"#;
+const CLOSE_ATTEMPTS: usize = 5;
 
 
+/// Moves the order to `new_status` and applies it to the stock in one transaction,
+/// with `publish` the event of the order as it is then is queued as well.
 #[instrument(skip_all)]
 pub(crate) async fn update_storage_and_order(
-    app_context: Arc<ModelManager>,
+    app_context: &ModelManager,
     order: &OrderStored,
     update_type: UpdateType,
     new_status: OrderStatus,
+    actor: &str,
+    reason: Option<&str>,
+    publish: bool,
 ) -> Result<()> {
-    let mut book_ids = Vec::with_capacity(order.content().len());
-    for order_item in order.content() {
-        let book_id = order_item.book_id();
-        book_ids.push(book_id);
+    let mut tx = app_context.pg_pool()
+        .begin()
+        .await?;
+
+    // the order row is locked first, a concurrent cancellation sees either all of the stock change or none
+    OrderBmc::transition_tx(&mut tx, order.order_id(), new_status, actor, reason).await?;
+    update_storage_tx(&mut tx, order, update_type).await?;
+    // the event is only visible to the relay if the status change commits,
+    // it is built from the updated order so its type follows the new status
+    if publish {
+        let updated = OrderBmc::get_by_id_tx(&mut tx, order.order_id()).await?;
+        OutboxBmc::insert_tx(&mut tx, &order_event(app_context.app_config(), &updated)?).await?;
     }
 
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Hands a ready order to its owner, the stock is consumed and the event queued as by a delivery.
+#[instrument(skip_all)]
+pub async fn pick_up_order(
+    app_context: &ModelManager,
+    order_id: i64,
+    actor: &str,
+) -> Result<OrderStored> {
+    let order = OrderBmc::get_by_id(app_context, order_id).await?;
+    update_storage_and_order(app_context, &order, UpdateType::Consume, OrderStatus::Delivered, actor, Some("picked up"), true).await?;
+    OrderBmc::get_by_id(app_context, order_id).await
+}
+
+/// Cancels the order if it wasn't delivered yet, see `close_order_tx`.
+#[instrument(skip_all)]
+pub async fn cancel_order(
+    app_context: &ModelManager,
+    order_id: i64,
+    actor: &str,
+    reason: Option<&str>,
+) -> Result<OrderStored> {
+    close_order(app_context, order_id, OrderStatus::Cancelled, actor, reason, None).await
+}
+
+/// Fails an order the pipeline can't process, see `close_order_tx`.
+#[instrument(skip_all)]
+pub(crate) async fn fail_order(
+    app_context: &ModelManager,
+    order_id: i64,
+    actor: &str,
+    reason: &str,
+) -> Result<OrderStored> {
+    close_order(app_context, order_id, OrderStatus::Failed, actor, Some(reason), None).await
+}
+
+/// Fails the order and keeps it as a dead letter, so an admin can requeue it.
+#[instrument(skip_all)]
+pub(crate) async fn dead_letter_order(
+    app_context: &ModelManager,
+    order_id: i64,
+    stage: &str,
+    attempts: u32,
+    last_error: &str,
+) -> Result<OrderStored> {
+    let attempts = i32::try_from(attempts).unwrap_or(i32::MAX);
+    close_order(app_context, order_id, OrderStatus::Failed, stage, Some(last_error), Some(attempts)).await
+}
+
+/// Sends a dead-lettered order through the pipeline again, from the start.
+#[instrument(skip_all)]
+pub async fn requeue_order(
+    app_context: &ModelManager,
+    order_id: i64,
+    actor: &str,
+) -> Result<OrderStored> {
     let mut tx = app_context.pg_pool()
         .begin()
         .await?;
 
-    tx_isolation_level(&mut tx, "REPEATABLE READ").await?;
+    if !FailedOrderBmc::mark_requeued_tx(&mut tx, order_id).await? {
+        return Err(Error::NotDeadLettered { order_id });
+    }
+    OrderBmc::transition_tx(&mut tx, order_id, OrderStatus::New, actor, Some("requeued")).await?;
+    let order = OrderBmc::get_by_id_tx(&mut tx, order_id).await?;
 
-    let book_storage_infos = StorageBmc::get_quantity_tx(&mut tx, book_ids).await?;
-    debug!("book_storage_info: {:#?}", book_storage_infos);
+    tx.commit().await?;
 
-    let map: HashMap<i64, i64> = book_storage_infos
-        .into_iter()
-        .map(|info| (info.id(), info.quantity().unwrap_or(0)))
-        .collect();
+    Ok(order)
+}
 
-    for order_item in order.content() {
-        let old_quantity = map.get(&order_item.book_id()).unwrap_or(&0i64);
-        let new_quantity = match update_type {
-            UpdateType::Add => {old_quantity + order_item.quantity()}
-            UpdateType::Remove => {old_quantity - order_item.quantity()}
-        } ;
-        let new_order_item = OrderItem::new(order_item.book_id(), new_quantity);
-        StorageBmc::update_storage_tx(&mut tx, &new_order_item).await?;
+/// Retried when Postgres aborts it to resolve a deadlock with a pipeline stage.
+async fn close_order(
+    app_context: &ModelManager,
+    order_id: i64,
+    to_status: OrderStatus,
+    actor: &str,
+    reason: Option<&str>,
+    dead_letter_attempts: Option<i32>,
+) -> Result<OrderStored> {
+    let mut attempt = 1;
+    loop {
+        match close_order_tx(app_context, order_id, to_status.clone(), actor, reason, dead_letter_attempts).await {
+            Err(e) if e.is_serialization_failure() && attempt < CLOSE_ATTEMPTS => {
+                debug!("retrying to close order {} because of {:#?}", order_id, e);
+                attempt += 1;
+            }
+            result => return result,
+        }
     }
+}
 
-    OrderBmc::update_status_tx(&mut tx, order.order_id(), new_status).await?;
+/// Moves the order to `Cancelled` or `Failed`, gives back what the storage stage applied
+/// and queues the event, all in one transaction.
+async fn close_order_tx(
+    app_context: &ModelManager,
+    order_id: i64,
+    to_status: OrderStatus,
+    actor: &str,
+    reason: Option<&str>,
+    dead_letter_attempts: Option<i32>,
+) -> Result<OrderStored> {
+    let mut tx = app_context.pg_pool()
+        .begin()
+        .await?;
+
+    let order = OrderBmc::get_by_id_tx(&mut tx, order_id).await?;
+    let from_status = OrderBmc::transition_tx(&mut tx, order_id, to_status, actor, reason).await?;
+    // the storage stage is the only one applied before delivery
+    if from_status == OrderStatus::ReadyToDeliver {
+        update_storage_tx(&mut tx, &order, UpdateType::Release).await?;
+    }
+    if let Some(attempts) = dead_letter_attempts {
+        FailedOrderBmc::insert_tx(&mut tx, order_id, actor, attempts, reason.unwrap_or_default()).await?;
+    }
+
+    let closed = OrderBmc::get_by_id_tx(&mut tx, order_id).await?;
+    OutboxBmc::insert_tx(&mut tx, &order_event(app_context.app_config(), &closed)?).await?;
 
     tx.commit().await?;
 
-    Ok(())
+    Ok(closed)
 }
 
-pub async fn tx_isolation_level(
+async fn update_storage_tx(
     tx: &mut Transaction<'_, Postgres>,
-    isolation_level: &str,
+    order: &OrderStored,
+    update_type: UpdateType,
 ) -> Result<()> {
-    let query = SET_TX_ISOLATION_LEVEL.to_string().add(isolation_level);
-    sqlx::query(&query)
-        .fetch_optional(& mut **tx)
-        .await?;
+    // quantities are summed per book and applied in book id order, so two orders can't deadlock
+    let mut quantities: BTreeMap<i64, i64> = BTreeMap::new();
+    for order_item in order.content() {
+        *quantities.entry(order_item.book_id()).or_default() += order_item.quantity();
+    }
+    debug!("updating storage for order {}: {:#?}", order.order_id(), quantities);
+
+    for (book_id, quantity) in quantities {
+        if StorageBmc::update_tx(tx, &update_type, book_id, quantity).await? {
+            continue;
+        }
+        let order_id = order.order_id();
+        return Err(match update_type {
+            UpdateType::Reserve => Error::InsufficientStock { order_id, book_id, requested: quantity },
+            UpdateType::Consume | UpdateType::Release => Error::MissingReservation { order_id, book_id },
+        });
+    }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,48 @@
+use sqlx::migrate::Migrator;
+
+use crate::context::app_context::ModelManager;
+use crate::error::{Error, Result};
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("../../../db/migrations-auth");
+
+pub struct MigrationBmc;
+
+const SELECT_APPLIED: &str = r#"
+SELECT version FROM _sqlx_migrations WHERE success = true;
+"#;
+
+const PING: &str = r#"
+SELECT 1;
+"#;
+
+impl MigrationBmc {
+    pub async fn ping(
+        mm: &ModelManager,
+    ) -> Result<()> {
+        sqlx::query(PING)
+            .execute(mm.pg_pool())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Fails with `MigrationsPending` until every migration embedded in the binary has been applied.
+    pub async fn check_applied(
+        mm: &ModelManager,
+    ) -> Result<()> {
+        let applied: Vec<i64> = sqlx::query_scalar(SELECT_APPLIED)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        let pending = MIGRATOR.iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .filter(|migration| !applied.contains(&migration.version))
+            .count();
+
+        if pending > 0 {
+            return Err(Error::MigrationsPending(pending));
+        }
+
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
+pub mod failed_order;
 pub mod general;
+pub mod migration;
 pub mod order;
+pub mod outbox;
 pub mod scheme;
 pub mod user;
 pub mod book_info;
+pub mod book_sales;
+pub mod consumer_offset;
 pub mod storage;
 
@@ -1,18 +1,23 @@
+use std::time::Duration;
+
 use chrono::prelude::*;
 use sqlx::{Postgres, Transaction};
 use tracing::log::info;
 
-use lib_dto::order::{OrderForCreate, OrderId, OrderStatus, OrderStored};
+use lib_dto::event::OrderRepublish;
+use lib_dto::order::{OrderForCreate, OrderId, OrderStatus, OrderStatusChange, OrderStored};
 
 use crate::context::app_context::ModelManager;
 use crate::error::Result;
+use crate::order::state_machine::ensure_transition;
+use crate::telemetry::current_traceparent;
 
 pub struct OrderBmc;
 const INSERT_ORDER: &str = r#"
 INSERT INTO order_info
-(user_id, content, status, created_at, updated_at)
+(user_id, content, status, created_at, updated_at, traceparent)
 VALUES
-($1, $2, $3, $4, $5)
+($1, $2, $3, $4, $5, $6)
 RETURNING order_id;
 "#;
 
@@ -20,13 +25,80 @@ const SELECT_BY_ID: &str = r#"
 SELECT * FROM order_info WHERE order_id=$1;
 "#;
 
+const SELECT_BY_IDS: &str = r#"
+SELECT * FROM order_info WHERE order_id = ANY($1);
+"#;
+
+/// Orders the pipeline didn't finish, untouched for at least `$1` milliseconds.
+const SELECT_UNFINISHED: &str = r#"
+SELECT * FROM order_info
+WHERE status IN ('new', 'in_progress', 'ready_to_deliver')
+  AND updated_at <= now() - $1 * interval '1 millisecond'
+ORDER BY order_id;
+"#;
+
+const SELECT_UPDATED_SINCE: &str = r#"
+SELECT * FROM order_info WHERE updated_at >= $1 ORDER BY updated_at, order_id;
+"#;
+
+/// Orders matching an `OrderRepublish`, a missing bound matches everything.
+const COUNT_MATCHING: &str = r#"
+SELECT count(*) FROM order_info
+WHERE ($1::order_status IS NULL OR status = $1)
+  AND ($2::timestamptz IS NULL OR created_at >= $2)
+  AND ($3::timestamptz IS NULL OR created_at <= $3)
+  AND ($4::timestamptz IS NULL OR updated_at >= $4)
+  AND ($5::timestamptz IS NULL OR updated_at <= $5);
+"#;
+
+/// The page of `COUNT_MATCHING` after order `$6`.
+const SELECT_MATCHING: &str = r#"
+SELECT * FROM order_info
+WHERE ($1::order_status IS NULL OR status = $1)
+  AND ($2::timestamptz IS NULL OR created_at >= $2)
+  AND ($3::timestamptz IS NULL OR created_at <= $3)
+  AND ($4::timestamptz IS NULL OR updated_at >= $4)
+  AND ($5::timestamptz IS NULL OR updated_at <= $5)
+  AND order_id > $6
+ORDER BY order_id
+LIMIT $7;
+"#;
+
+const SELECT_LATEST_UPDATE: &str = r#"
+SELECT max(updated_at) FROM order_info;
+"#;
+
+/// Other pollers skip the rows locked here, so every new order is claimed once.
+const SELECT_NEW_FOR_UPDATE: &str = r#"
+SELECT order_id FROM order_info
+WHERE status = 'new'
+ORDER BY order_id
+LIMIT $1
+FOR UPDATE SKIP LOCKED;
+"#;
+
+const SELECT_STATUS_FOR_UPDATE: &str = r#"
+SELECT status FROM order_info WHERE order_id=$1 FOR UPDATE;
+"#;
+
 const UPDATE_STATUS: &str = r#"
 UPDATE order_info
-SET status = $1
+SET status = $1, updated_at = now()
 WHERE order_id = $2
 RETURNING order_id;
 "#;
 
+const INSERT_HISTORY: &str = r#"
+INSERT INTO order_status_history
+(order_id, from_status, to_status, actor, reason)
+VALUES
+($1, $2, $3, $4, $5);
+"#;
+
+const SELECT_HISTORY: &str = r#"
+SELECT * FROM order_status_history WHERE order_id=$1 ORDER BY id;
+"#;
+
 const CLEANUP_ORDERS: &str = r#"
 TRUNCATE order_info CASCADE;
 "#;
@@ -37,16 +109,22 @@ impl OrderBmc {
         order: OrderForCreate,
     ) -> Result<OrderId> {
 
+        let mut tx = mm.pg_pool().begin().await?;
         let json = sqlx::types::Json::from(order.content());
-        let order_id = sqlx::query_as(INSERT_ORDER)
+        let order_id: OrderId = sqlx::query_as(INSERT_ORDER)
             .bind(order.user_id())
             .bind(json)
             .bind(OrderStatus::New)
             .bind(Utc::now())
             .bind(Utc::now())
-            .fetch_one(mm.pg_pool())
+            .bind(current_traceparent())
+            .fetch_one(&mut *tx)
             .await?;
 
+        let actor = format!("user:{}", order.user_id());
+        insert_history_tx(&mut tx, order_id.order_id(), None, &OrderStatus::New, &actor, None).await?;
+        tx.commit().await?;
+
         Ok(order_id)
     }
 
@@ -63,34 +141,173 @@ impl OrderBmc {
         Ok(order)
     }
 
-    pub async fn update_status(
+    /// Orders which no longer exist are left out.
+    pub async fn get_by_ids(
+        mm: &ModelManager,
+        order_ids: &[i64],
+    ) -> Result<Vec<OrderStored>> {
+        let orders = sqlx::query_as(SELECT_BY_IDS)
+            .bind(order_ids)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(orders)
+    }
+
+    /// Orders still `new`, `in_progress` or `ready_to_deliver` which nothing touched for `untouched_for`, oldest first.
+    pub async fn get_unfinished(
+        mm: &ModelManager,
+        untouched_for: Duration,
+    ) -> Result<Vec<OrderStored>> {
+        let orders = sqlx::query_as(SELECT_UNFINISHED)
+            .bind(untouched_for.as_millis() as i64)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(orders)
+    }
+
+    /// Orders changed at or after `since`, oldest change first.
+    pub async fn get_updated_since(
+        mm: &ModelManager,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<OrderStored>> {
+        let orders = sqlx::query_as(SELECT_UPDATED_SINCE)
+            .bind(since)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(orders)
+    }
+
+    pub async fn count_matching(
+        mm: &ModelManager,
+        filter: &OrderRepublish,
+    ) -> Result<i64> {
+        let count = sqlx::query_scalar(COUNT_MATCHING)
+            .bind(filter.status())
+            .bind(filter.created_from())
+            .bind(filter.created_to())
+            .bind(filter.updated_from())
+            .bind(filter.updated_to())
+            .fetch_one(mm.pg_pool())
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Up to `limit` matching orders with an id above `after_order_id`, by id.
+    pub async fn get_matching(
         mm: &ModelManager,
+        filter: &OrderRepublish,
+        after_order_id: i64,
+        limit: i64,
+    ) -> Result<Vec<OrderStored>> {
+        let orders = sqlx::query_as(SELECT_MATCHING)
+            .bind(filter.status())
+            .bind(filter.created_from())
+            .bind(filter.created_to())
+            .bind(filter.updated_from())
+            .bind(filter.updated_to())
+            .bind(after_order_id)
+            .bind(limit)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(orders)
+    }
+
+    pub async fn latest_update(mm: &ModelManager) -> Result<Option<DateTime<Utc>>> {
+        let latest: Option<DateTime<Utc>> = sqlx::query_scalar(SELECT_LATEST_UPDATE)
+            .fetch_one(mm.pg_pool())
+            .await?;
+
+        Ok(latest)
+    }
+
+    /// Moves up to `limit` new orders to `InProgress`, returns their ids.
+    pub async fn claim_new(
+        mm: &ModelManager,
+        limit: i64,
+        actor: &str,
+    ) -> Result<Vec<i64>> {
+        let mut tx = mm.pg_pool().begin().await?;
+        let order_ids: Vec<i64> = sqlx::query_scalar(SELECT_NEW_FOR_UPDATE)
+            .bind(limit)
+            .fetch_all(&mut *tx)
+            .await?;
+        for order_id in &order_ids {
+            Self::transition_tx(&mut tx, *order_id, OrderStatus::InProgress, actor, None).await?;
+        }
+        tx.commit().await?;
+
+        Ok(order_ids)
+    }
+
+    pub async fn get_by_id_tx(
+        tx: &mut Transaction<'_, Postgres>,
         order_id: i64,
-        order_status: OrderStatus,
-    ) -> Result<()> {
-        info!("Trying to update order with id: {:#?}, new status: {:#?}", &order_id, &order_status);
-        sqlx::query(UPDATE_STATUS)
-            .bind(&order_status)
+    ) -> Result<OrderStored> {
+        let order: OrderStored = sqlx::query_as(SELECT_BY_ID)
             .bind(order_id)
-            .execute(mm.pg_pool())
+            .fetch_one(&mut **tx)
             .await?;
 
-        Ok(())
+        Ok(order)
     }
 
-    pub async fn update_status_tx(
+    /// Moves the order to `to_status` in its own transaction, see `transition_tx`.
+    pub async fn transition(
+        mm: &ModelManager,
+        order_id: i64,
+        to_status: OrderStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<OrderStatus> {
+        let mut tx = mm.pg_pool().begin().await?;
+        let from_status = Self::transition_tx(&mut tx, order_id, to_status, actor, reason).await?;
+        tx.commit().await?;
+
+        Ok(from_status)
+    }
+
+    /// Moves the order to `to_status` if the state machine allows it, records the change
+    /// and returns the previous status.
+    /// The order row stays locked until the transaction ends, so concurrent moves are serialized.
+    pub async fn transition_tx(
         tx: &mut Transaction<'_, Postgres>,
         order_id: i64,
-        order_status: OrderStatus,
-    ) -> Result<()> {
-        info!("Trying to update order with id: {:#?}, new status: {:#?}", &order_id, &order_status);
+        to_status: OrderStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<OrderStatus> {
+        info!("Trying to move order with id: {:#?} to status: {:#?} by {}", &order_id, &to_status, actor);
+        let from_status: OrderStatus = sqlx::query_scalar(SELECT_STATUS_FOR_UPDATE)
+            .bind(order_id)
+            .fetch_one(&mut **tx)
+            .await?;
+        ensure_transition(order_id, &from_status, &to_status)?;
+
         sqlx::query(UPDATE_STATUS)
-            .bind(&order_status)
+            .bind(&to_status)
             .bind(order_id)
             .execute(&mut **tx)
             .await?;
+        insert_history_tx(tx, order_id, Some(&from_status), &to_status, actor, reason).await?;
 
-        Ok(())
+        Ok(from_status)
+    }
+
+    pub async fn history(
+        mm: &ModelManager,
+        order_id: i64,
+    ) -> Result<Vec<OrderStatusChange>> {
+        let history = sqlx::query_as(SELECT_HISTORY)
+            .bind(order_id)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(history)
     }
 
     pub async fn cleanup_orders(
@@ -104,4 +321,22 @@ impl OrderBmc {
     }
 }
 
+async fn insert_history_tx(
+    tx: &mut Transaction<'_, Postgres>,
+    order_id: i64,
+    from_status: Option<&OrderStatus>,
+    to_status: &OrderStatus,
+    actor: &str,
+    reason: Option<&str>,
+) -> Result<()> {
+    sqlx::query(INSERT_HISTORY)
+        .bind(order_id)
+        .bind(from_status)
+        .bind(to_status)
+        .bind(actor)
+        .bind(reason)
+        .execute(&mut **tx)
+        .await?;
 
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use sqlx::{Postgres, Transaction};
+
+use lib_dto::outbox::{OutboxForCreate, OutboxMessage};
+
+use crate::context::app_context::ModelManager;
+use crate::error::Result;
+
+pub struct OutboxBmc;
+
+const INSERT_OUTBOX: &str = r#"
+INSERT INTO outbox (topic, message_key, payload, traceparent)
+VALUES ($1, $2, $3, $4);
+"#;
+
+/// Claims due rows for `$2` milliseconds, so a relay which dies while publishing
+/// leaves them to be picked up again once the lease expires.
+const CLAIM_PENDING: &str = r#"
+UPDATE outbox
+SET attempts = attempts + 1,
+    next_attempt_at = now() + $2 * interval '1 millisecond'
+WHERE id IN (
+    SELECT id FROM outbox
+    WHERE sent_at IS NULL AND next_attempt_at <= now()
+    ORDER BY id
+    LIMIT $1
+    FOR UPDATE SKIP LOCKED
+)
+RETURNING *;
+"#;
+
+const MARK_SENT: &str = r#"
+UPDATE outbox
+SET sent_at = now(), last_error = NULL
+WHERE id = $1;
+"#;
+
+const SCHEDULE_RETRY: &str = r#"
+UPDATE outbox
+SET next_attempt_at = now() + $2 * interval '1 millisecond', last_error = $3
+WHERE id = $1;
+"#;
+
+const COUNT_PENDING: &str = r#"
+SELECT count(*) FROM outbox WHERE sent_at IS NULL;
+"#;
+
+impl OutboxBmc {
+    pub async fn insert_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        message: &OutboxForCreate,
+    ) -> Result<()> {
+        sqlx::query(INSERT_OUTBOX)
+            .bind(message.topic())
+            .bind(message.message_key())
+            .bind(sqlx::types::Json(message.payload()))
+            .bind(message.traceparent())
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn claim_pending(
+        mm: &ModelManager,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>> {
+        let messages = sqlx::query_as(CLAIM_PENDING)
+            .bind(limit)
+            .bind(lease.as_millis() as f64)
+            .fetch_all(mm.pg_pool())
+            .await?;
+
+        Ok(messages)
+    }
+
+    pub async fn mark_sent(
+        mm: &ModelManager,
+        id: i64,
+    ) -> Result<()> {
+        sqlx::query(MARK_SENT)
+            .bind(id)
+            .execute(mm.pg_pool())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn schedule_retry(
+        mm: &ModelManager,
+        id: i64,
+        delay: Duration,
+        error: &str,
+    ) -> Result<()> {
+        sqlx::query(SCHEDULE_RETRY)
+            .bind(id)
+            .bind(delay.as_millis() as f64)
+            .bind(error)
+            .execute(mm.pg_pool())
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn count_pending(
+        mm: &ModelManager,
+    ) -> Result<i64> {
+        let (count,): (i64,) = sqlx::query_as(COUNT_PENDING)
+            .fetch_one(mm.pg_pool())
+            .await?;
+
+        Ok(count)
+    }
+}
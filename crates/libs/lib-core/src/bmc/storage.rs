@@ -11,7 +11,8 @@ pub struct StorageBmc;
 const SELECT_JOIN_STORAGE: &str = r#"
 SELECT
     bi.id,
-    bs.quantity
+    bs.quantity,
+    bs.reserved
 FROM
     book_info as bi
 FULL OUTER JOIN book_storage as bs
@@ -24,13 +25,44 @@ INSERT INTO book_storage (book_id, quantity) values ($1, $2)
 ON CONFLICT (book_id) DO UPDATE SET quantity = $2;
 "#;
 
+const RESTOCK: &str = r#"
+INSERT INTO book_storage (book_id, quantity) values ($1, $2)
+ON CONFLICT (book_id) DO UPDATE SET quantity = book_storage.quantity + $2, updated_at = now();
+"#;
+
+const RESERVE: &str = r#"
+UPDATE book_storage
+SET reserved = reserved + $2, updated_at = now()
+WHERE book_id = $1 AND quantity - reserved >= $2
+RETURNING book_id;
+"#;
+
+const CONSUME: &str = r#"
+UPDATE book_storage
+SET quantity = quantity - $2, reserved = reserved - $2, updated_at = now()
+WHERE book_id = $1 AND reserved >= $2
+RETURNING book_id;
+"#;
+
+const RELEASE: &str = r#"
+UPDATE book_storage
+SET reserved = reserved - $2, updated_at = now()
+WHERE book_id = $1 AND reserved >= $2
+RETURNING book_id;
+"#;
+
 const CLEANUP_STORAGE: &str = r#"
 TRUNCATE book_storage CASCADE;
 "#;
 
+/// How an order changes the stock of its books.
 pub(crate) enum UpdateType {
-    Add,
-    Remove,
+    /// Takes available stock aside for the order.
+    Reserve,
+    /// Turns the reservation into a decrement of the stock.
+    Consume,
+    /// Gives the reservation back.
+    Release,
 }
 
 impl StorageBmc {
@@ -39,25 +71,13 @@ impl StorageBmc {
         book_id: i64,
     ) -> Result<BookStorageInfo> {
         let book_storage: BookStorageInfo = sqlx::query_as(SELECT_JOIN_STORAGE)
-            .bind(book_id)
+            .bind(vec![book_id])
             .fetch_one(mm.pg_pool())
             .await?;
 
         Ok(book_storage)
     }
 
-    pub async fn get_quantity_tx(
-        tx: &mut Transaction<'_, Postgres>,
-        book_ids: Vec<i64>,
-    ) -> Result<Vec<BookStorageInfo>> {
-        let book_storage: Vec<BookStorageInfo> = sqlx::query_as(SELECT_JOIN_STORAGE)
-            .bind(&book_ids)
-            .fetch_all(& mut **tx)
-            .await?;
-
-        Ok(book_storage)
-    }
-
     pub async fn update_storage(
         mm: &ModelManager,
         item: &OrderItem,
@@ -71,19 +91,46 @@ impl StorageBmc {
         Ok(())
     }
 
-    pub async fn update_storage_tx(
-        tx: &mut Transaction<'_, Postgres>,
-        item: &OrderItem,
+    /// Adds `quantity` of every item to the stock.
+    pub async fn restock(
+        mm: &ModelManager,
+        items: &[OrderItem],
     ) -> Result<()> {
-        sqlx::query(UPDATE_STORAGE)
-            .bind(item.book_id())
-            .bind(item.quantity())
-            .fetch_optional(& mut **tx)
-            .await?;
+        let mut tx = mm.pg_pool().begin().await?;
+        for item in items {
+            sqlx::query(RESTOCK)
+                .bind(item.book_id())
+                .bind(item.quantity())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
 
         Ok(())
     }
 
+    /// Applies the update to one book, checked and applied by a single statement.
+    /// Returns false when the stock or the reservation is short, nothing is changed then.
+    pub(crate) async fn update_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        update_type: &UpdateType,
+        book_id: i64,
+        quantity: i64,
+    ) -> Result<bool> {
+        let query = match update_type {
+            UpdateType::Reserve => RESERVE,
+            UpdateType::Consume => CONSUME,
+            UpdateType::Release => RELEASE,
+        };
+        let updated: Option<i64> = sqlx::query_scalar(query)
+            .bind(book_id)
+            .bind(quantity)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        Ok(updated.is_some())
+    }
+
     pub async fn cleanup_storage(
         mm: &ModelManager,
     ) -> Result<()> {
@@ -93,4 +140,4 @@ impl StorageBmc {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use rdkafka::{ClientConfig, ClientContext, Message, Offset, TopicPartitionList};
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer};
+use rdkafka::error::RDKafkaErrorCode;
+use rdkafka::message::{BorrowedMessage, Header, Headers, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::util::Timeout;
+use tokio::select;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::bus::{BusConsumer, BusMessage, BusRecord, MessageBus, StartOffsets};
+use crate::context::app_context::AppConfig;
+use crate::task::actor::BoxFuture;
+
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+const SEND_TIMEOUT: Duration = Duration::from_secs(2);
+const SEEK_TIMEOUT: Duration = Duration::from_secs(1);
+const ADMIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The brokers at `AppConfig::kafka_url`, one producer is shared by every publisher.
+pub(crate) struct KafkaBus {
+    app_config: AppConfig,
+    producer: FutureProducer,
+}
+
+impl KafkaBus {
+    pub(crate) fn new(app_config: &AppConfig) -> Result<Self> {
+        Ok(Self { app_config: app_config.clone(), producer: producer(app_config)? })
+    }
+}
+
+impl MessageBus for KafkaBus {
+    fn publish<'a>(&'a self, record: &'a BusRecord) -> BoxFuture<'a, Result<(i32, i64)>> {
+        Box::pin(async move {
+            let headers = record.headers().iter()
+                .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                    headers.insert(Header { key, value: Some(value) })
+                });
+            let kafka_record = FutureRecord::to(record.topic())
+                .payload(record.payload())
+                .key(record.key())
+                .headers(headers);
+            match self.producer.send(kafka_record, Timeout::After(SEND_TIMEOUT)).await {
+                Ok(delivered) => Ok(delivered),
+                Err((e, _)) => Err(e.into()),
+            }
+        })
+    }
+
+    fn subscribe<'a>(&'a self, topics: &'a [&'a str], group_id: &'a str) -> BoxFuture<'a, Result<Box<dyn BusConsumer>>> {
+        Box::pin(async move { self.consumer(topics, group_id, None) })
+    }
+
+    fn subscribe_from<'a>(
+        &'a self,
+        topics: &'a [&'a str],
+        group_id: &'a str,
+        start_offsets: Arc<dyn StartOffsets>,
+    ) -> BoxFuture<'a, Result<Box<dyn BusConsumer>>> {
+        Box::pin(async move { self.consumer(topics, group_id, Some(start_offsets)) })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        let producer = self.producer.clone();
+        Box::pin(async move {
+            // flush blocks the calling thread until the queue is empty or the timeout expires
+            tokio::task::spawn_blocking(move || producer.flush(Timeout::After(FLUSH_TIMEOUT))).await??;
+            Ok(())
+        })
+    }
+
+    /// Creates the missing topics as configured in `AppConfig::kafka_topics`.
+    /// An existing topic is kept, a partition count differing from the configured one is only logged.
+    fn ensure_topics<'a>(&'a self, topics: &'a [&'a str]) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let topic_config = &self.app_config.kafka_topics;
+            let retention = topic_config.retention.map(|retention| retention.as_millis().to_string());
+            let new_topics: Vec<NewTopic> = topics.iter()
+                .map(|topic| {
+                    let new_topic = NewTopic::new(topic, topic_config.partitions, TopicReplication::Fixed(topic_config.replication_factor));
+                    match &retention {
+                        Some(retention) => new_topic.set("retention.ms", retention),
+                        None => new_topic,
+                    }
+                })
+                .collect();
+
+            let options = AdminOptions::new()
+                .request_timeout(Some(ADMIN_TIMEOUT))
+                .operation_timeout(Some(ADMIN_TIMEOUT));
+            let mut existing = Vec::new();
+            for result in admin(&self.app_config)?.create_topics(&new_topics, &options).await? {
+                match result {
+                    Ok(topic) => info!("Created topic {}", topic),
+                    Err((topic, RDKafkaErrorCode::TopicAlreadyExists)) => existing.push(topic),
+                    Err((topic, code)) => bail!("broker rejected topic {}: {}", topic, code),
+                }
+            }
+
+            let producer = self.producer.clone();
+            let partitions = topic_config.partitions;
+            // fetching metadata blocks the calling thread
+            tokio::task::spawn_blocking(move || {
+                for topic in existing {
+                    let metadata = producer.client().fetch_metadata(Some(&topic), ADMIN_TIMEOUT)?;
+                    let found = metadata.topics().first().map_or(0, |topic| topic.partitions().len());
+                    if found != partitions as usize {
+                        warn!("Topic {} has {} partitions instead of {}, it is left as it is", topic, found, partitions);
+                    }
+                }
+                Ok::<_, anyhow::Error>(())
+            }).await??;
+            Ok(())
+        })
+    }
+}
+
+impl KafkaBus {
+    fn consumer(
+        &self,
+        topics: &[&str],
+        group_id: &str,
+        start_offsets: Option<Arc<dyn StartOffsets>>,
+    ) -> Result<Box<dyn BusConsumer>> {
+        let consumer = consumer(&self.app_config, group_id)?;
+        consumer.subscribe(topics)?;
+        let consumer: Box<dyn BusConsumer> = Box::new(KafkaConsumer {
+            consumer: Arc::new(consumer),
+            start_offsets,
+            floors: Mutex::default(),
+            received: Mutex::default(),
+        });
+        Ok(consumer)
+    }
+}
+
+/// Collects the partitions assigned by a rebalance, so the consumer moves them to their start offsets.
+#[derive(Default)]
+struct AssignmentContext {
+    assigned: Mutex<Vec<(String, i32)>>,
+    notify: Notify,
+}
+
+impl AssignmentContext {
+    fn assigned(&self) -> MutexGuard<'_, Vec<(String, i32)>> {
+        // the lock is never held across a panic point, recover the data anyway
+        self.assigned.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ClientContext for AssignmentContext {}
+
+impl ConsumerContext for AssignmentContext {
+    /// Runs within `StreamConsumer::recv`, which serves the rebalance events.
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        if let Rebalance::Assign(partitions) = rebalance {
+            self.assigned().extend(partitions.elements().iter()
+                .map(|partition| (partition.topic().to_string(), partition.partition())));
+            self.notify.notify_one();
+        }
+    }
+}
+
+struct KafkaConsumer {
+    consumer: Arc<StreamConsumer<AssignmentContext>>,
+    start_offsets: Option<Arc<dyn StartOffsets>>,
+    // per partition, the start offset it was sought to, messages fetched before the seek are below it
+    floors: Mutex<HashMap<(String, i32), i64>>,
+    // received but not handed out yet, kept when `recv` is cancelled
+    received: Mutex<Option<BusMessage>>,
+}
+
+impl KafkaConsumer {
+    fn floors(&self) -> MutexGuard<'_, HashMap<(String, i32), i64>> {
+        self.floors.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn received(&self) -> MutexGuard<'_, Option<BusMessage>> {
+        self.received.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Seeks the partitions assigned since the last call to their start offsets,
+    /// where those are later than the committed ones.
+    async fn start_assigned(&self) -> Result<()> {
+        let Some(start_offsets) = &self.start_offsets else {
+            self.consumer.context().assigned().clear();
+            return Ok(());
+        };
+        loop {
+            // removed once it is sought, so a cancelled recv continues with it
+            let next = self.consumer.context().assigned().first().cloned();
+            let Some((topic, partition)) = next else {
+                return Ok(());
+            };
+            if let Some(offset) = start_offsets.start_offset(&topic, partition).await? {
+                self.floors().insert((topic.clone(), partition), offset);
+                let consumer = self.consumer.clone();
+                // reading the committed offset and seeking block the calling thread
+                let sought = tokio::task::spawn_blocking(move || {
+                    seek_forward(&consumer, &topic, partition, offset)
+                        .map_err(|e| e.context(format!("failed to seek {}/{} to {}", topic, partition, offset)))
+                }).await?;
+                if let Err(e) = sought {
+                    // the floor still keeps the processed messages from being handed out
+                    warn!("{:#}", e);
+                }
+            }
+            self.consumer.context().assigned().remove(0);
+        }
+    }
+}
+
+impl BusConsumer for KafkaConsumer {
+    fn recv(&self) -> BoxFuture<'_, Result<BusMessage>> {
+        Box::pin(async move {
+            loop {
+                // receiving serves the rebalances, their partitions are sought before anything is handed out
+                self.start_assigned().await?;
+                if let Some(message) = self.received().take() {
+                    let floor = self.floors().get(&(message.topic().to_string(), message.partition())).copied();
+                    if floor.is_some_and(|floor| message.offset() < floor) {
+                        debug!("Skipping {}/{}@{}, fetched before the seek to {:?}", message.topic(), message.partition(), message.offset(), floor);
+                        continue;
+                    }
+                    return Ok(message);
+                }
+                select! {
+                    biased;
+                    _ = self.consumer.context().notify.notified() => {}
+                    message = self.consumer.recv() => match message {
+                        Err(e) => error!("{:?}", e),
+                        Ok(message) => *self.received() = Some(bus_message(&message)),
+                    },
+                }
+            }
+        })
+    }
+
+    fn commit(&self, message: &BusMessage) -> Result<()> {
+        let mut offsets = TopicPartitionList::new();
+        offsets.add_partition_offset(message.topic(), message.partition(), Offset::Offset(message.offset() + 1))?;
+        self.consumer.commit(&offsets, CommitMode::Async)?;
+        Ok(())
+    }
+
+    fn commit_sync(&self) -> Result<()> {
+        self.consumer.commit_consumer_state(CommitMode::Sync)?;
+        Ok(())
+    }
+
+    fn unsubscribe(&self) {
+        self.consumer.unsubscribe();
+    }
+}
+
+fn bus_message(message: &BorrowedMessage<'_>) -> BusMessage {
+    let headers = message.headers()
+        .map(|headers| headers.iter()
+            .filter_map(|header| {
+                let value = std::str::from_utf8(header.value?).ok()?;
+                Some((header.key.to_string(), value.to_string()))
+            })
+            .collect())
+        .unwrap_or_default();
+    BusMessage::new(
+        message.topic(),
+        message.partition(),
+        message.offset(),
+        message.key().map(|key| String::from_utf8_lossy(key).into_owned()),
+        message.payload().map(<[u8]>::to_vec),
+        headers,
+    )
+}
+
+/// Continues `partition` of `topic` at `offset` unless the group committed a later one.
+fn seek_forward(consumer: &StreamConsumer<AssignmentContext>, topic: &str, partition: i32, offset: i64) -> Result<()> {
+    let mut partitions = TopicPartitionList::new();
+    partitions.add_partition(topic, partition);
+    let committed = consumer.committed_offsets(partitions, SEEK_TIMEOUT)?;
+    if let Some(Offset::Offset(committed)) = committed.find_partition(topic, partition).map(|partition| partition.offset()) {
+        if committed >= offset {
+            return Ok(());
+        }
+    }
+    info!("Seeking {}/{} to {}", topic, partition, offset);
+    consumer.seek(topic, partition, Offset::Offset(offset), SEEK_TIMEOUT)?;
+    Ok(())
+}
+
+#[instrument(skip_all)]
+fn producer(app_config: &AppConfig) -> Result<FutureProducer> {
+    info!("Creating kafka producer");
+
+    let producer_config = &app_config.kafka_producer;
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", app_config.kafka_url.as_str())
+        .set("acks", producer_config.acks.as_str())
+        .set("enable.idempotence", producer_config.enable_idempotence.to_string())
+        .set("linger.ms", producer_config.linger.as_millis().to_string())
+        .set("compression.type", producer_config.compression.as_str());
+
+    info!("config: {:#?}", &config);
+
+    let producer : FutureProducer = config.create()?;
+
+    Ok(producer)
+}
+
+fn admin(app_config: &AppConfig) -> Result<AdminClient<DefaultClientContext>> {
+    let admin = ClientConfig::new()
+        .set("bootstrap.servers", app_config.kafka_url.as_str())
+        .create()?;
+    Ok(admin)
+}
+
+/// Offsets are committed by the application, never automatically.
+fn consumer(app_config: &AppConfig, group_id: &str) -> Result<StreamConsumer<AssignmentContext>> {
+    info!("Creating Kafka Consumer");
+
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", app_config.kafka_url.as_str())
+        .set("auto.offset.reset", "earliest")
+        .set("group.id", group_id)
+        .set("enable.auto.commit", "false")
+        .set("socket.timeout.ms","1000");
+
+    let consumer : StreamConsumer<AssignmentContext> = config.create_with_context(AssignmentContext::default())?;
+
+    Ok(consumer)
+}
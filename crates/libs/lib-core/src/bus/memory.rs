@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Result;
+use tokio::sync::broadcast;
+
+use crate::bus::{BusConsumer, BusMessage, BusRecord, MessageBus, StartOffsets};
+use crate::task::actor::BoxFuture;
+
+/// Wake-ups a consumer may miss before it rescans, it never misses a message.
+const WAKE_CAPACITY: usize = 16;
+
+/// Topics kept as partitioned logs in memory, a consumer group continues after its committed offsets.
+///
+/// Unlike Kafka, the partitions aren't split between the members of a group, every member reads all of them.
+pub(crate) struct InMemoryBus {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    partitions: i32,
+    logs: Mutex<Logs>,
+    // every publish wakes the waiting consumers
+    appended: broadcast::Sender<()>,
+}
+
+#[derive(Default)]
+struct Logs {
+    topics: HashMap<String, Vec<Vec<BusMessage>>>,
+    // (group, topic, partition) to the offset of the next message
+    committed: HashMap<(String, String, i32), i64>,
+}
+
+impl InMemoryBus {
+    pub(crate) fn new(partitions: i32) -> Self {
+        let (appended, _) = broadcast::channel(WAKE_CAPACITY);
+        Self { inner: Arc::new(Inner { partitions: partitions.max(1), logs: Mutex::default(), appended }) }
+    }
+}
+
+impl Inner {
+    fn logs(&self) -> MutexGuard<'_, Logs> {
+        // the lock is never held across a panic point, recover the data anyway
+        self.logs.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn partition(&self, key: &str) -> i32 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.partitions as u64) as i32
+    }
+}
+
+impl MessageBus for InMemoryBus {
+    fn publish<'a>(&'a self, record: &'a BusRecord) -> BoxFuture<'a, Result<(i32, i64)>> {
+        let partition = self.inner.partition(record.key());
+        let stored = {
+            let mut logs = self.inner.logs();
+            let log = &mut logs.topics.entry(record.topic().to_string())
+                .or_insert_with(|| vec![Vec::new(); self.inner.partitions as usize])[partition as usize];
+            let offset = log.len() as i64;
+            log.push(BusMessage::new(
+                record.topic(),
+                partition,
+                offset,
+                Some(record.key().to_string()),
+                Some(record.payload().to_vec()),
+                record.headers().to_vec(),
+            ));
+            (partition, offset)
+        };
+        // nobody may be waiting
+        let _ = self.inner.appended.send(());
+        Box::pin(async move { Ok(stored) })
+    }
+
+    fn subscribe<'a>(&'a self, topics: &'a [&'a str], group_id: &'a str) -> BoxFuture<'a, Result<Box<dyn BusConsumer>>> {
+        let consumer: Box<dyn BusConsumer> = Box::new(self.consumer(topics, group_id));
+        Box::pin(async move { Ok(consumer) })
+    }
+
+    /// Every partition is assigned on subscribing, so that is when the start offsets are looked up.
+    fn subscribe_from<'a>(
+        &'a self,
+        topics: &'a [&'a str],
+        group_id: &'a str,
+        start_offsets: Arc<dyn StartOffsets>,
+    ) -> BoxFuture<'a, Result<Box<dyn BusConsumer>>> {
+        Box::pin(async move {
+            let consumer = self.consumer(topics, group_id);
+            let assignments = consumer.positions().assignments.clone();
+            for (topic, partition) in assignments {
+                if let Some(offset) = start_offsets.start_offset(&topic, partition).await? {
+                    consumer.start_at(&topic, partition, offset);
+                }
+            }
+            let consumer: Box<dyn BusConsumer> = Box::new(consumer);
+            Ok(consumer)
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<()>> {
+        // published messages are stored at once
+        Box::pin(async { Ok(()) })
+    }
+
+    fn ensure_topics<'a>(&'a self, _topics: &'a [&'a str]) -> BoxFuture<'a, Result<()>> {
+        // a topic is created by its first message, with the partitions of the bus
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl InMemoryBus {
+    fn consumer(&self, topics: &[&str], group_id: &str) -> InMemoryConsumer {
+        let assignments = topics.iter()
+            .flat_map(|topic| (0..self.inner.partitions).map(|partition| (topic.to_string(), partition)))
+            .collect();
+        InMemoryConsumer {
+            inner: self.inner.clone(),
+            group_id: group_id.to_string(),
+            subscribed: AtomicBool::new(true),
+            positions: Mutex::new(Positions { assignments, offsets: HashMap::new(), next: 0 }),
+            // subscribed before the first scan, so nothing appended after it goes unnoticed
+            wake: tokio::sync::Mutex::new(self.inner.appended.subscribe()),
+        }
+    }
+}
+
+struct InMemoryConsumer {
+    inner: Arc<Inner>,
+    group_id: String,
+    subscribed: AtomicBool,
+    positions: Mutex<Positions>,
+    // held by the recv in progress
+    wake: tokio::sync::Mutex<broadcast::Receiver<()>>,
+}
+
+struct Positions {
+    assignments: Vec<(String, i32)>,
+    // offset of the next message per assignment, starts at the committed one
+    offsets: HashMap<(String, i32), i64>,
+    // where the next scan starts, so a busy partition doesn't starve the others
+    next: usize,
+}
+
+impl InMemoryConsumer {
+    fn positions(&self) -> MutexGuard<'_, Positions> {
+        self.positions.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Continues `partition` of `topic` at `offset` unless the group committed a later one.
+    fn start_at(&self, topic: &str, partition: i32, offset: i64) {
+        let mut positions = self.positions();
+        let logs = self.inner.logs();
+        let committed = logs.committed.get(&(self.group_id.clone(), topic.to_string(), partition)).copied().unwrap_or(0);
+        positions.offsets.insert((topic.to_string(), partition), offset.max(committed));
+    }
+
+    /// Takes the next message of the first assignment which has one, round robin.
+    fn poll(&self) -> Option<BusMessage> {
+        let mut positions = self.positions();
+        let logs = self.inner.logs();
+        let assigned = positions.assignments.len();
+        for i in 0..assigned {
+            let index = (positions.next + i) % assigned;
+            let (topic, partition) = positions.assignments[index].clone();
+            let key = (topic.clone(), partition);
+            let position = *positions.offsets.entry(key.clone()).or_insert_with(|| {
+                logs.committed.get(&(self.group_id.clone(), topic.clone(), partition)).copied().unwrap_or(0)
+            });
+            let message = logs.topics.get(&topic)
+                .and_then(|partitions| partitions[partition as usize].get(position as usize));
+            if let Some(message) = message {
+                positions.offsets.insert(key, position + 1);
+                positions.next = index + 1;
+                return Some(message.clone());
+            }
+        }
+        None
+    }
+}
+
+impl BusConsumer for InMemoryConsumer {
+    fn recv(&self) -> BoxFuture<'_, Result<BusMessage>> {
+        Box::pin(async move {
+            let mut wake = self.wake.lock().await;
+            loop {
+                if !self.subscribed.load(Ordering::Acquire) {
+                    return std::future::pending().await;
+                }
+                if let Some(message) = self.poll() {
+                    return Ok(message);
+                }
+                // a lagged receiver just scans again, the sender lives as long as the bus
+                let _ = wake.recv().await;
+            }
+        })
+    }
+
+    fn commit(&self, message: &BusMessage) -> Result<()> {
+        let key = (self.group_id.clone(), message.topic().to_string(), message.partition());
+        let mut logs = self.inner.logs();
+        let committed = logs.committed.entry(key).or_default();
+        *committed = (*committed).max(message.offset() + 1);
+        Ok(())
+    }
+
+    fn commit_sync(&self) -> Result<()> {
+        let positions = self.positions();
+        let mut logs = self.inner.logs();
+        for ((topic, partition), position) in &positions.offsets {
+            let committed = logs.committed.entry((self.group_id.clone(), topic.clone(), *partition)).or_default();
+            *committed = (*committed).max(*position);
+        }
+        Ok(())
+    }
+
+    fn unsubscribe(&self) {
+        self.subscribed.store(false, Ordering::Release);
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn record(key: &str, payload: &str) -> BusRecord {
+        BusRecord::new("orders", key, payload)
+    }
+
+    async fn recv_payloads(consumer: &dyn BusConsumer, count: usize) -> Vec<String> {
+        let mut payloads = Vec::new();
+        for _ in 0..count {
+            let message = consumer.recv().await.expect("must be ok");
+            payloads.push(message.payload_str().expect("must be ok").to_string());
+        }
+        payloads
+    }
+
+    #[tokio::test]
+    async fn test_same_key_keeps_partition_and_order() {
+        let bus = InMemoryBus::new(4);
+        let (partition, first) = bus.publish(&record("7", "a")).await.expect("must be ok");
+        bus.publish(&record("8", "x")).await.expect("must be ok");
+        let (same_partition, second) = bus.publish(&record("7", "b").with_header("traceparent", "t")).await.expect("must be ok");
+        assert_eq!(partition, same_partition);
+        assert!(first < second);
+
+        let consumer = bus.subscribe(&["orders"], "group").await.expect("must be ok");
+        let mut of_seven = Vec::new();
+        for _ in 0..3 {
+            let message = consumer.recv().await.expect("must be ok");
+            if message.key() == Some("7") {
+                of_seven.push((message.payload_str().expect("must be ok").to_string(), message.header("traceparent").map(str::to_string)));
+            }
+        }
+        assert_eq!(vec![("a".to_string(), None), ("b".to_string(), Some("t".to_string()))], of_seven);
+    }
+
+    #[tokio::test]
+    async fn test_group_continues_after_commit() {
+        let bus = InMemoryBus::new(1);
+        for payload in ["a", "b", "c"] {
+            bus.publish(&record("1", payload)).await.expect("must be ok");
+        }
+
+        let consumer = bus.subscribe(&["orders"], "group").await.expect("must be ok");
+        let first = consumer.recv().await.expect("must be ok");
+        consumer.commit(&first).expect("must be ok");
+        // received but never committed
+        consumer.recv().await.expect("must be ok");
+
+        let restarted = bus.subscribe(&["orders"], "group").await.expect("must be ok");
+        assert_eq!(vec!["b", "c"], recv_payloads(restarted.as_ref(), 2).await);
+        let other_group = bus.subscribe(&["orders"], "other").await.expect("must be ok");
+        assert_eq!(vec!["a"], recv_payloads(other_group.as_ref(), 1).await);
+    }
+
+    struct FixedOffset(i64);
+
+    impl StartOffsets for FixedOffset {
+        fn start_offset<'a>(&'a self, _topic: &'a str, _partition: i32) -> BoxFuture<'a, Result<Option<i64>>> {
+            Box::pin(async move { Ok(Some(self.0)) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_later_of_start_and_committed_offset() {
+        let bus = InMemoryBus::new(1);
+        for payload in ["a", "b", "c", "d"] {
+            bus.publish(&record("1", payload)).await.expect("must be ok");
+        }
+        let consumer = bus.subscribe(&["orders"], "group").await.expect("must be ok");
+        consumer.commit(&consumer.recv().await.expect("must be ok")).expect("must be ok");
+
+        let ahead = bus.subscribe_from(&["orders"], "group", Arc::new(FixedOffset(3))).await.expect("must be ok");
+        assert_eq!(vec!["d"], recv_payloads(ahead.as_ref(), 1).await);
+        let behind = bus.subscribe_from(&["orders"], "group", Arc::new(FixedOffset(0))).await.expect("must be ok");
+        assert_eq!(vec!["b"], recv_payloads(behind.as_ref(), 1).await);
+    }
+
+    #[tokio::test]
+    async fn test_recv_waits_for_publish() {
+        let bus = Arc::new(InMemoryBus::new(2));
+        let consumer = bus.subscribe(&["orders"], "group").await.expect("must be ok");
+
+        // a cancelled recv loses nothing
+        let timed_out = tokio::time::timeout(Duration::from_millis(20), consumer.recv()).await;
+        assert!(timed_out.is_err());
+
+        let publisher = bus.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            publisher.publish(&record("1", "a")).await.expect("must be ok");
+        });
+        assert_eq!(vec!["a"], recv_payloads(consumer.as_ref(), 1).await);
+    }
+}
+// endregion: --- Tests
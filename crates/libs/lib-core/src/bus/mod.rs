@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use crate::context::app_context::{AppConfig, ModelManager};
+use crate::notify::source::OrderSourceConfig;
+use crate::task::actor::BoxFuture;
+
+pub(crate) mod kafka;
+pub(crate) mod memory;
+
+/// Partitions of every topic of the in-memory bus unless configured otherwise.
+pub const DEFAULT_PARTITIONS: i32 = 3;
+
+/// Publishes and consumes the messages of the pipeline, Kafka or an in-process stand-in of it.
+///
+/// Messages with the same key go to the same partition, a partition keeps the order they were published in.
+pub trait MessageBus: Send + Sync {
+    /// Resolves once the message is stored, with the partition and offset it got.
+    fn publish<'a>(&'a self, record: &'a BusRecord) -> BoxFuture<'a, Result<(i32, i64)>>;
+
+    /// Joins `group_id` on `topics`, a group continues after the offsets it committed.
+    fn subscribe<'a>(&'a self, topics: &'a [&'a str], group_id: &'a str) -> BoxFuture<'a, Result<Box<dyn BusConsumer>>>;
+
+    /// Like `subscribe`, but a partition assigned to the consumer continues at the later
+    /// of its committed offset and the one of `start_offsets`.
+    fn subscribe_from<'a>(
+        &'a self,
+        topics: &'a [&'a str],
+        group_id: &'a str,
+        start_offsets: Arc<dyn StartOffsets>,
+    ) -> BoxFuture<'a, Result<Box<dyn BusConsumer>>>;
+
+    /// Waits until everything published is stored.
+    fn flush(&self) -> BoxFuture<'_, Result<()>>;
+
+    /// Creates the missing `topics`, fails if they can't be created.
+    fn ensure_topics<'a>(&'a self, topics: &'a [&'a str]) -> BoxFuture<'a, Result<()>>;
+}
+
+/// The consuming side of a `MessageBus` subscription.
+pub trait BusConsumer: Send + Sync {
+    /// Waits for the next message, must be cancel safe.
+    fn recv(&self) -> BoxFuture<'_, Result<BusMessage>>;
+
+    /// Commits the offset after `message` without waiting for it, a later commit covers a lost one.
+    fn commit(&self, message: &BusMessage) -> Result<()>;
+
+    /// Commits what was consumed so far and waits for it.
+    fn commit_sync(&self) -> Result<()>;
+
+    /// Leaves the group, nothing is received anymore.
+    fn unsubscribe(&self);
+}
+
+/// Offsets a consumer keeps outside of the bus, looked up when a partition is assigned to it.
+pub trait StartOffsets: Send + Sync {
+    /// Offset of the next message of `partition` of `topic` to consume, none if nothing is stored.
+    fn start_offset<'a>(&'a self, topic: &'a str, partition: i32) -> BoxFuture<'a, Result<Option<i64>>>;
+}
+
+/// Which `MessageBus` the pipeline runs on.
+#[derive(Clone, Debug, Default)]
+pub enum MessageBusConfig {
+    /// The brokers at `AppConfig::kafka_url`.
+    #[default]
+    Kafka,
+    /// Kept in memory, nothing outlives the process. For local development and tests.
+    InMemory { partitions: i32 },
+}
+
+/// A message to publish.
+#[derive(Clone, Debug)]
+pub struct BusRecord {
+    topic: String,
+    key: String,
+    payload: Vec<u8>,
+    headers: Vec<(String, String)>,
+}
+
+impl BusRecord {
+    pub fn new(topic: impl Into<String>, key: impl Into<String>, payload: impl Into<Vec<u8>>) -> Self {
+        Self { topic: topic.into(), key: key.into(), payload: payload.into(), headers: Vec::new() }
+    }
+
+    pub fn with_header(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+}
+
+/// A consumed message.
+#[derive(Clone, Debug)]
+pub struct BusMessage {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    key: Option<String>,
+    payload: Option<Vec<u8>>,
+    headers: Vec<(String, String)>,
+}
+
+impl BusMessage {
+    pub fn new(
+        topic: impl Into<String>,
+        partition: i32,
+        offset: i64,
+        key: Option<String>,
+        payload: Option<Vec<u8>>,
+        headers: Vec<(String, String)>,
+    ) -> Self {
+        Self { topic: topic.into(), partition, offset, key, payload, headers }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn partition(&self) -> i32 {
+        self.partition
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    pub fn payload(&self) -> Option<&[u8]> {
+        self.payload.as_deref()
+    }
+
+    /// The payload as text, an empty or binary one is an error.
+    pub fn payload_str(&self) -> Result<&str> {
+        let payload = self.payload.as_deref().ok_or_else(|| anyhow!("empty message"))?;
+        Ok(std::str::from_utf8(payload)?)
+    }
+
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(header, _)| header == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+}
+
+/// Makes sure the topics the pipeline publishes to and consumes from exist, before any task starts.
+pub async fn provision_topics(mm: &ModelManager) -> Result<()> {
+    let app_config = mm.app_config();
+    let mut topics = vec![app_config.kafka_producer.topic.as_str(), app_config.dead_letter.topic.as_str()];
+    if let OrderSourceConfig::Kafka { topic } = &app_config.order_source {
+        topics.push(topic.as_str());
+    }
+    mm.message_bus().ensure_topics(&topics).await
+}
+
+/// Builds the bus configured in `AppConfig::message_bus`.
+pub(crate) fn create(app_config: &AppConfig) -> Result<Arc<dyn MessageBus>> {
+    let bus: Arc<dyn MessageBus> = match app_config.message_bus {
+        MessageBusConfig::Kafka => Arc::new(kafka::KafkaBus::new(app_config)?),
+        MessageBusConfig::InMemory { partitions } => Arc::new(memory::InMemoryBus::new(partitions)),
+    };
+    Ok(bus)
+}
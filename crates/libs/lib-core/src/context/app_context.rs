@@ -2,16 +2,28 @@ use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
 use std::ops::Deref;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use axum::body::Body;
 use hyper_util::client::legacy::Client;
 use hyper_util::client::legacy::connect::HttpConnector;
 use sqlx::postgres::PgPool;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
+use lib_dto::order::OrderStored;
+
+use crate::bus::{self, MessageBus, MessageBusConfig};
+use crate::notify::listener::ListenerStats;
+use crate::notify::source::OrderSourceConfig;
+use crate::task::kafka::{ConsumerOffsets, DeadLetterConfig, KafkaProducerConfig, KafkaTopicConfig};
 use crate::task::main_task::MainTaskRequest;
+use crate::task::retry::RetryPolicy;
+
+/// Updates a subscriber may lag behind before it starts missing them.
+const ORDER_UPDATES_CAPACITY: usize = 256;
 
 #[derive(Clone)]
 pub struct ModelManager {
@@ -22,6 +34,9 @@ pub struct ModelManager {
     app_config: AppConfig,
     cancellation_token: CancellationToken,
     db_mutex: Arc<Mutex<()>>,
+    order_updates: broadcast::Sender<OrderStored>,
+    listener_stats: Arc<ListenerStats>,
+    message_bus: Arc<dyn MessageBus>,
 }
 
 impl ModelManager {
@@ -40,6 +55,11 @@ impl ModelManager {
 
         let db_mutex = Arc::new(Mutex::new(()));
 
+        let (order_updates, _) = broadcast::channel(ORDER_UPDATES_CAPACITY);
+
+        // only a malformed producer setting fails here, the brokers are connected to lazily
+        let message_bus = bus::create(&app_config).expect("message bus must be configured correctly");
+
         ModelManager {
             main_tx,
             pg_pool,
@@ -48,6 +68,9 @@ impl ModelManager {
             app_config,
             cancellation_token,
             db_mutex,
+            order_updates,
+            listener_stats: Arc::default(),
+            message_bus,
         }
     }
 
@@ -77,6 +100,26 @@ impl ModelManager {
         &self.db_mutex
     }
 
+    /// Orders as they are updated in the database, fed by `NotifyTask`.
+    pub fn subscribe_order_updates(&self) -> broadcast::Receiver<OrderStored> {
+        self.order_updates.subscribe()
+    }
+
+    /// Fans the update out to the current subscribers, returns how many got it.
+    pub(crate) fn publish_order_update(&self, order: OrderStored) -> usize {
+        // sending only fails when nobody is subscribed
+        self.order_updates.send(order).unwrap_or(0)
+    }
+
+    pub fn listener_stats(&self) -> &ListenerStats {
+        &self.listener_stats
+    }
+
+    /// The bus configured in `AppConfig::message_bus`, shared by the producer and the consumers.
+    pub fn message_bus(&self) -> &Arc<dyn MessageBus> {
+        &self.message_bus
+    }
+
     pub fn main_tx(&self) -> Sender<MainTaskRequest> {
         self.main_tx.clone()
     }
@@ -86,6 +129,24 @@ impl ModelManager {
 pub struct AppConfig {
     pub auth_url: Arc<String>,
     pub kafka_url: Arc<String>,
+    /// How long a graceful shutdown waits for queued orders before cancelling the tasks.
+    pub shutdown_deadline: Duration,
+    /// How many orders `OrderTask` processes concurrently.
+    pub order_workers: usize,
+    /// Phones of the users allowed to call the admin RPC methods.
+    pub admin_phones: Arc<Vec<String>>,
+    /// Retries of the storage and delivery updates before the order is dead-lettered.
+    pub update_retry: RetryPolicy,
+    /// An unfinished order untouched for this long is sent through the pipeline again.
+    pub stale_order_after: Duration,
+    /// Where the orders entering the pipeline come from.
+    pub order_source: OrderSourceConfig,
+    pub kafka_producer: KafkaProducerConfig,
+    pub kafka_topics: KafkaTopicConfig,
+    /// What the order events are published to and consumed from.
+    pub message_bus: MessageBusConfig,
+    pub consumer_offsets: ConsumerOffsets,
+    pub dead_letter: DeadLetterConfig,
 }
 
 impl Debug for ModelManager {
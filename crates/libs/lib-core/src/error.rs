@@ -3,6 +3,8 @@ use std::env::VarError;
 use thiserror::Error;
 use tracing::error;
 
+use lib_dto::order::OrderStatus;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Error, Debug)]
@@ -13,10 +15,44 @@ pub enum Error {
     CoreError,
     #[error("Wrong password")]
     WrongPassword,
+    #[error("Migrations pending: {0}")]
+    MigrationsPending(usize),
+    #[error("Order {order_id} can't move from {from:?} to {to:?}")]
+    IllegalTransition { order_id: i64, from: OrderStatus, to: OrderStatus },
+    #[error("Not enough stock of book {book_id} for {requested} items of order {order_id}")]
+    InsufficientStock { order_id: i64, book_id: i64, requested: i64 },
+    #[error("No reservation of book {book_id} for order {order_id}")]
+    MissingReservation { order_id: i64, book_id: i64 },
+    #[error("Order {order_id} is not dead-lettered")]
+    NotDeadLettered { order_id: i64 },
+    #[error("Book {0} not found")]
+    BookNotFound(String),
+    #[error("Another book already has {field} {value}")]
+    BookConflict { field: &'static str, value: String },
+    #[error("Serde json error: {0}")]
+    SerdeJson(#[from] serde_json::Error),
     #[error("Var error: {0}")]
     VarError(#[from] VarError),
 }
 
+impl Error {
+    /// Retrying the same update can't succeed, the order has to be rejected.
+    pub fn is_order_rejection(&self) -> bool {
+        matches!(
+            self,
+            Error::IllegalTransition { .. } | Error::InsufficientStock { .. } | Error::MissingReservation { .. }
+        )
+    }
+
+    /// A concurrent transaction won, retrying the whole transaction may succeed.
+    pub fn is_serialization_failure(&self) -> bool {
+        match self {
+            Error::Sqlx(sqlx::Error::Database(e)) => matches!(e.code().as_deref(), Some("40001") | Some("40P01")),
+            _ => false,
+        }
+    }
+}
+
 impl From<sqlx::Error> for Error {
     fn from(value: sqlx::Error) -> Self {
         error!("Sqlx error: {:#?}", &value);
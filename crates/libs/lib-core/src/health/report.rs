@@ -0,0 +1,130 @@
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use rdkafka::admin::AdminClient;
+use rdkafka::client::DefaultClientContext;
+use rdkafka::ClientConfig;
+use serde::Serialize;
+use tokio::time::Instant;
+use tracing::{instrument, warn};
+
+use crate::bmc::migration::MigrationBmc;
+use crate::bus::MessageBusConfig;
+use crate::context::app_context::{AppConfig, ModelManager};
+
+/// Upper bound for a single check, so one stuck dependency can't hang the whole report.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Up,
+    Down,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CheckReport {
+    name: String,
+    status: CheckStatus,
+    elapsed_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl CheckReport {
+    pub fn down(name: impl Into<String>, error: impl Into<String>) -> Self {
+        Self { name: name.into(), status: CheckStatus::Down, elapsed_ms: 0, error: Some(error.into()) }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn status(&self) -> CheckStatus {
+        self.status
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthReport {
+    status: CheckStatus,
+    checks: Vec<CheckReport>,
+}
+
+impl HealthReport {
+    pub fn new(checks: Vec<CheckReport>) -> Self {
+        let status = if checks.iter().all(|check| check.status == CheckStatus::Up) {
+            CheckStatus::Up
+        } else {
+            CheckStatus::Down
+        };
+        Self { status, checks }
+    }
+
+    pub fn status(&self) -> CheckStatus {
+        self.status
+    }
+
+    pub fn checks(&self) -> &Vec<CheckReport> {
+        &self.checks
+    }
+}
+
+/// Runs a single check with `CHECK_TIMEOUT`, a timeout is reported as `Down`.
+pub async fn check<F>(name: impl Into<String>, future: F) -> CheckReport
+where
+    F: Future<Output = Result<()>>,
+{
+    let name = name.into();
+    let started_at = Instant::now();
+    let error = match tokio::time::timeout(CHECK_TIMEOUT, future).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("timed out after {:?}", CHECK_TIMEOUT)),
+    };
+    if let Some(error) = &error {
+        warn!("Health check {} failed: {}", &name, error);
+    }
+    let status = if error.is_none() { CheckStatus::Up } else { CheckStatus::Down };
+
+    CheckReport { name, status, elapsed_ms: started_at.elapsed().as_millis(), error }
+}
+
+#[instrument(skip_all)]
+pub async fn check_postgres(mm: &ModelManager) -> CheckReport {
+    check("postgres", async { Ok(MigrationBmc::ping(mm).await?) }).await
+}
+
+#[instrument(skip_all)]
+pub async fn check_migrations(mm: &ModelManager) -> CheckReport {
+    check("migrations", async { Ok(MigrationBmc::check_applied(mm).await?) }).await
+}
+
+/// Probes the brokers of the Kafka bus, the in-memory bus lives in the process and is always up.
+#[instrument(skip_all)]
+pub async fn check_message_bus(app_config: &AppConfig) -> CheckReport {
+    match app_config.message_bus {
+        MessageBusConfig::Kafka => check_kafka(app_config).await,
+        MessageBusConfig::InMemory { .. } => check("in_memory_bus", async { Ok(()) }).await,
+    }
+}
+
+#[instrument(skip_all)]
+async fn check_kafka(app_config: &AppConfig) -> CheckReport {
+    let kafka_url = app_config.kafka_url.clone();
+    check("kafka", async move {
+        // fetching metadata blocks the calling thread
+        tokio::task::spawn_blocking(move || {
+            let admin: AdminClient<DefaultClientContext> = ClientConfig::new()
+                .set("bootstrap.servers", kafka_url.as_str())
+                .create()?;
+            admin.inner().fetch_metadata(None, CHECK_TIMEOUT)?;
+            Ok(())
+        }).await?
+    }).await
+}
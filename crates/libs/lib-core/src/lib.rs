@@ -1,6 +1,10 @@
 pub mod context;
 pub mod bmc;
+pub mod bus;
 pub mod notify;
+pub mod order;
+pub mod health;
 pub mod error;
 pub mod task;
+pub mod telemetry;
 pub mod macro_util;
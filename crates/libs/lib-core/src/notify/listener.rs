@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Written by `NotifyTask`, read by anyone holding the `Arc`.
+///
+/// It lives in `ModelManager` rather than in the task, so a restarted task still knows
+/// from where to catch up.
+#[derive(Debug, Default)]
+pub struct ListenerStats {
+    connected: AtomicBool,
+    reconnects: AtomicU64,
+    progress: Mutex<Progress>,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    last_seen: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+impl ListenerStats {
+    pub fn status(&self) -> ListenerStatus {
+        let progress = self.progress();
+        ListenerStatus {
+            connected: self.connected.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            last_seen: progress.last_seen,
+            last_error: progress.last_error.clone(),
+        }
+    }
+
+    /// `updated_at` of the latest order change seen, changes after it are caught up on reconnect.
+    pub(crate) fn last_seen(&self) -> Option<DateTime<Utc>> {
+        self.progress().last_seen
+    }
+
+    pub(crate) fn seen(&self, updated_at: DateTime<Utc>) {
+        let mut progress = self.progress();
+        if progress.last_seen.is_none_or(|last_seen| last_seen < updated_at) {
+            progress.last_seen = Some(updated_at);
+        }
+    }
+
+    pub(crate) fn connected(&self) {
+        self.connected.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn disconnected(&self, error: Option<String>) {
+        self.connected.store(false, Ordering::Relaxed);
+        if error.is_some() {
+            self.progress().last_error = error;
+        }
+    }
+
+    pub(crate) fn reconnected(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+        self.connected();
+    }
+
+    fn progress(&self) -> MutexGuard<'_, Progress> {
+        // the lock is never held across a panic point, recover the data anyway
+        self.progress.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Snapshot of the notification listener.
+#[derive(Clone, Debug, Serialize)]
+pub struct ListenerStatus {
+    connected: bool,
+    reconnects: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_seen: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+impl ListenerStatus {
+    pub fn connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    pub fn last_seen(&self) -> Option<DateTime<Utc>> {
+        self.last_seen
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn test_last_seen_only_moves_forward() {
+        let stats = ListenerStats::default();
+        let now = Utc::now();
+        stats.seen(now);
+        stats.seen(now - TimeDelta::seconds(1));
+        assert_eq!(Some(now), stats.last_seen());
+    }
+
+    #[test]
+    fn test_reconnects() {
+        let stats = ListenerStats::default();
+        stats.connected();
+        stats.disconnected(Some("connection reset".to_string()));
+        let status = stats.status();
+        assert!(!status.connected());
+        assert_eq!(Some("connection reset"), status.last_error());
+
+        stats.reconnected();
+        let status = stats.status();
+        assert!(status.connected());
+        assert_eq!(1, status.reconnects());
+        assert_eq!(Some("connection reset"), status.last_error());
+    }
+}
+// endregion: --- Tests
@@ -1,2 +1,3 @@
+pub mod listener;
 pub mod order;
-
+pub mod source;
@@ -1,101 +1,124 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::sync::Arc;
 
 use anyhow::Result;
-use serde::Deserialize;
-use sqlx::postgres::PgListener;
-use tokio::select;
-use tokio::sync::oneshot;
-use tracing::{error, info, instrument};
+use tracing::{debug, error, info, instrument};
 
 use lib_dto::order::OrderStored;
 
+use crate::bmc::order::OrderBmc;
 use crate::context::app_context::ModelManager;
-use crate::task::main_task::{TaskManager};
-use crate::task::order::OrderRequest;
+use crate::notify::source::{self, OrderEvent, OrderEventSource};
+use crate::task::actor::{Actor, ActorHandle};
+use crate::task::main_task::TaskManager;
+use crate::task::order::{submit, OrderRequest, OrderTask};
+
+/// The notify task is driven by its `OrderEventSource`, it takes no requests besides the built-in ones.
+#[derive(Debug)]
+pub enum NotifyRequest {}
+
+/// Routes the order events of the source configured in `AppConfig::order_source`.
+///
+/// An event only carries the order id, the orders are loaded in batches.
+pub(crate) struct NotifyTask {
+    app_context: Arc<ModelManager>,
+    source: Box<dyn OrderEventSource>,
+    order: ActorHandle<OrderRequest>,
+}
 
-pub(crate) struct NotifyTask {}
+impl Actor for NotifyTask {
+    type Request = NotifyRequest;
+    type Event = Vec<OrderEvent>;
+
+    const NAME: &'static str = "NotifyTask";
 
-impl NotifyTask {
     #[instrument(skip_all)]
-    pub(crate) async fn start(app_context: Arc<ModelManager>) {
-        info!("Starting notify task");
-
-        let cancellation_token = app_context.cancellation_token();
-        let jh = tokio::spawn(async move {
-            select! {
-                _ = handle_notify(app_context) => {}
-                _ = cancellation_token.cancelled() => {
-                    info!("Cancelled by cancellation token.")
-                }
-            }
-        }).await;
+    async fn create(app_context: Arc<ModelManager>) -> Result<Self> {
+        let source = source::create(app_context.clone()).await?;
+        info!("Getting order handle");
+        let order = TaskManager::actor::<OrderTask>(app_context.main_tx()).await?;
+        info!("Got order handle");
+
+        Ok(Self { app_context, source, order })
+    }
 
-        match jh {
-            Ok(_) => {
-                info!("Notify task completed.")
+    async fn handle(&mut self, request: NotifyRequest) -> Result<()> {
+        match request {}
+    }
+
+    async fn next_event(&mut self) -> Result<Vec<OrderEvent>> {
+        self.source.next_events().await
+    }
+
+    /// An order is loaded once per batch and its update is published once, with its latest state.
+    #[instrument(skip_all)]
+    async fn handle_event(&mut self, events: Vec<OrderEvent>) -> Result<()> {
+        // a deleted order can't be loaded anymore, nor does it need to be
+        let mut order_ids: Vec<i64> = events.iter()
+            .filter(|event| !matches!(event, OrderEvent::Deleted(_)))
+            .map(OrderEvent::order_id)
+            .collect();
+        order_ids.sort_unstable();
+        order_ids.dedup();
+        let orders: HashMap<i64, OrderStored> = if order_ids.is_empty() {
+            HashMap::new()
+        } else {
+            OrderBmc::get_by_ids(&self.app_context, &order_ids).await?
+                .into_iter()
+                .map(|order| (order.order_id(), order))
+                .collect()
+        };
+
+        let mut published = HashSet::new();
+        for event in events {
+            if let OrderEvent::Deleted(order_id) = event {
+                self.abort(order_id).await;
+                continue;
             }
-            Err(e) => {
-                error!("Error during notify task: {:#?}", e)
+            let Some(order) = orders.get(&event.order_id()) else {
+                info!("Order {} is gone, skipping {:?}", event.order_id(), event);
+                continue;
+            };
+            if let OrderEvent::Updated(order_id) = event {
+                if !published.insert(order_id) {
+                    continue;
+                }
             }
+            self.route(event, order.clone()).await;
         }
+        Ok(())
     }
-}
-
-
-#[derive(Deserialize, Debug)]
-pub enum ActionType {
-    INSERT,
-    UPDATE,
-    DELETE,
-}
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct OrderPayload {
-    table: String,
-    action_type: ActionType,
+    /// Stops taking events, so no new orders enter the pipeline.
+    async fn drain(&mut self) {
+        self.source.close().await;
+    }
 }
 
-#[instrument(skip_all)]
-pub async fn handle_notify(
-    app_context: Arc<ModelManager>
-) -> Result<()> {
-    info!("Starting handle_notify");
-    let main_tx = app_context.main_tx();
-
-    let channels = vec!["table_update"];
-
-    let mut listener = PgListener::connect_with(app_context.pg_pool()).await.unwrap();
-    listener.listen_all(channels).await.expect("error");
-    info!("Getting order tx");
-    let order_tx = TaskManager::order_sender(main_tx.clone()).await?;
-    info!("Got order tx");
-
-    loop {
-        while let Some(notification) = listener.try_recv().await.expect("error") {
-            let strr = notification.payload().to_owned();
-            let payload: OrderPayload = serde_json::from_str::<OrderPayload>(&strr).unwrap();
-            let order_stored: OrderStored = serde_json::from_str::<OrderStored>(&strr).unwrap();
-            info!("the payload is {:#?}", &payload);
-            info!("the order stored is {:#?}", &order_stored);
-
-            match payload.action_type {
-                ActionType::INSERT => {
-                    let (result_tx, result_rx) = oneshot::channel();
-                    order_tx.send(OrderRequest::ProcessOrder(order_stored, result_tx)).await
-                        .expect("TODO: panic message");
-                    let res = result_rx.await.expect("failed to receive result");
-                    info!("Received order response: {:#?}", res);
-                }
-                ActionType::UPDATE => {
-
+impl NotifyTask {
+    async fn route(&self, event: OrderEvent, order_stored: OrderStored) {
+        info!("Got {:?}", event);
+        self.app_context.listener_stats().seen(order_stored.updated_at());
+
+        match event {
+            OrderEvent::Created(_) => {
+                if let Err(e) = submit(&self.order, order_stored).await {
+                    error!("Failed to send order: {:#?}", e);
                 }
-                ActionType::DELETE => {
+            }
+            OrderEvent::Updated(_) => {
+                let subscribers = self.app_context.publish_order_update(order_stored);
+                debug!("Order update sent to {} subscribers", subscribers);
+            }
+            OrderEvent::Deleted(order_id) => self.abort(order_id).await,
+        };
+    }
 
-                }
-            };
-            info!(" ");
+    async fn abort(&self, order_id: i64) {
+        info!("Got deletion of order {}", order_id);
+        if let Err(e) = self.order.cast(OrderRequest::Abort(order_id)).await {
+            error!("Failed to abort order {}: {:#?}", order_id, e);
         }
     }
 }
@@ -0,0 +1,71 @@
+use anyhow::Result;
+use tracing::{error, info};
+
+use crate::bus::BusConsumer;
+use crate::context::app_context::ModelManager;
+use crate::notify::source::{parse, OrderEvent, OrderEventSource};
+use crate::task::actor::BoxFuture;
+
+const GROUP_ID: &str = "order-source";
+
+/// Consumes the notification payloads, `{table, order_id, action_type}`, from a topic of the `MessageBus`.
+pub(crate) struct KafkaSource {
+    // None once closed
+    consumer: Option<Box<dyn BusConsumer>>,
+}
+
+impl KafkaSource {
+    pub(crate) async fn subscribe(app_context: &ModelManager, topic: &str) -> Result<Self> {
+        info!("Subscribing order source to {}", topic);
+        let consumer = app_context.message_bus().subscribe(&[topic], GROUP_ID).await?;
+        Ok(Self { consumer: Some(consumer) })
+    }
+
+    async fn next(&mut self) -> Result<Vec<OrderEvent>> {
+        let Some(consumer) = self.consumer.as_ref() else {
+            return std::future::pending().await;
+        };
+        loop {
+            let message = match consumer.recv().await {
+                Ok(message) => message,
+                Err(e) => {
+                    error!("Failed to receive order event: {:?}", e);
+                    continue;
+                }
+            };
+            let event = match message.payload_str() {
+                Ok(payload) => match parse(payload) {
+                    Ok(event) => Some(event),
+                    Err(e) => {
+                        error!("Skipping malformed order event {}: {:#?}", payload, e);
+                        None
+                    }
+                },
+                Err(e) => {
+                    error!("Skipping order event without a text payload: {:#}", e);
+                    None
+                }
+            };
+            // the event is committed once it is handed over, an order it misses is left to RecoveryTask
+            if let Err(e) = consumer.commit(&message) {
+                error!("Failed to commit order event: {:?}", e);
+            }
+            if let Some(event) = event {
+                return Ok(vec![event]);
+            }
+        }
+    }
+}
+
+impl OrderEventSource for KafkaSource {
+    fn next_events(&mut self) -> BoxFuture<'_, Result<Vec<OrderEvent>>> {
+        Box::pin(self.next())
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, ()> {
+        if let Some(consumer) = self.consumer.take() {
+            consumer.unsubscribe();
+        }
+        Box::pin(async {})
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::context::app_context::ModelManager;
+use crate::task::actor::BoxFuture;
+
+pub(crate) mod kafka;
+pub(crate) mod pg_listener;
+pub(crate) mod polling;
+
+/// Events already waiting are handed out together, so their orders are loaded at once.
+pub(crate) const EVENT_BATCH: usize = 100;
+
+/// Something which happened to an order, only the id is carried, the order is loaded by the receiver.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderEvent {
+    Created(i64),
+    Updated(i64),
+    Deleted(i64),
+}
+
+impl OrderEvent {
+    pub fn order_id(&self) -> i64 {
+        match self {
+            OrderEvent::Created(order_id) | OrderEvent::Updated(order_id) | OrderEvent::Deleted(order_id) => *order_id,
+        }
+    }
+}
+
+/// Where `NotifyTask` takes the order events from.
+pub trait OrderEventSource: Send + Sync {
+    /// Waits for the next events, must be cancel safe.
+    fn next_events(&mut self) -> BoxFuture<'_, Result<Vec<OrderEvent>>>;
+
+    /// Stops taking events, so no new orders enter the pipeline.
+    fn close(&mut self) -> BoxFuture<'_, ()>;
+}
+
+/// Which `OrderEventSource` feeds the pipeline.
+#[derive(Clone, Debug, Default)]
+pub enum OrderSourceConfig {
+    /// `LISTEN` on the channel notified by the `order_info` triggers.
+    #[default]
+    Listen,
+    /// Claims new orders with `SELECT ... FOR UPDATE SKIP LOCKED`, for connections which can't `LISTEN`,
+    /// e.g. behind PgBouncer in transaction mode. Updates and deletions are not seen.
+    Poll { interval: Duration },
+    /// Consumes the notification payloads from a topic of the `MessageBus`.
+    Kafka { topic: String },
+    /// Events sent through the sender of `OrderEventChannel::new`, for tests.
+    Channel(OrderEventChannel),
+}
+
+/// Lets events be injected directly, the receiver is shared so it outlives restarts of `NotifyTask`.
+#[derive(Clone)]
+pub struct OrderEventChannel {
+    rx: Arc<Mutex<Receiver<OrderEvent>>>,
+}
+
+impl OrderEventChannel {
+    pub fn new(capacity: usize) -> (Sender<OrderEvent>, Self) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (tx, Self { rx: Arc::new(Mutex::new(rx)) })
+    }
+}
+
+impl Debug for OrderEventChannel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("OrderEventChannel")
+    }
+}
+
+/// Builds the source configured in `AppConfig::order_source`.
+pub(crate) async fn create(app_context: Arc<ModelManager>) -> Result<Box<dyn OrderEventSource>> {
+    let source: Box<dyn OrderEventSource> = match app_context.app_config().order_source.clone() {
+        OrderSourceConfig::Listen => Box::new(pg_listener::PgListenerSource::connect(app_context).await?),
+        OrderSourceConfig::Poll { interval } => Box::new(polling::PollingSource::new(app_context, interval)),
+        OrderSourceConfig::Kafka { topic } => Box::new(kafka::KafkaSource::subscribe(&app_context, &topic).await?),
+        OrderSourceConfig::Channel(channel) => Box::new(ChannelSource::new(channel).await),
+    };
+    Ok(source)
+}
+
+struct ChannelSource {
+    // None once closed
+    rx: Option<OwnedMutexGuard<Receiver<OrderEvent>>>,
+}
+
+impl ChannelSource {
+    async fn new(channel: OrderEventChannel) -> Self {
+        Self { rx: Some(channel.rx.lock_owned().await) }
+    }
+}
+
+impl OrderEventSource for ChannelSource {
+    fn next_events(&mut self) -> BoxFuture<'_, Result<Vec<OrderEvent>>> {
+        Box::pin(async move {
+            let Some(rx) = self.rx.as_mut() else {
+                return std::future::pending().await;
+            };
+            // every sender is gone, nothing will come anymore
+            let Some(event) = rx.recv().await else {
+                return std::future::pending().await;
+            };
+            let mut events = vec![event];
+            while events.len() < EVENT_BATCH {
+                let Ok(event) = rx.try_recv() else {
+                    break;
+                };
+                events.push(event);
+            }
+            Ok(events)
+        })
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, ()> {
+        self.rx = None;
+        Box::pin(async {})
+    }
+}
+
+/// `TG_OP` of the trigger.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+enum ActionType {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// The payload sent by the `order_info` triggers, also used on the Kafka source.
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+struct OrderPayload {
+    table: String,
+    order_id: i64,
+    action_type: ActionType,
+}
+
+pub(crate) fn parse(payload: &str) -> serde_json::Result<OrderEvent> {
+    let payload: OrderPayload = serde_json::from_str(payload)?;
+    let event = match payload.action_type {
+        ActionType::Insert => OrderEvent::Created(payload.order_id),
+        ActionType::Update => OrderEvent::Updated(payload.order_id),
+        ActionType::Delete => OrderEvent::Deleted(payload.order_id),
+    };
+    Ok(event)
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_update() {
+        let payload = r#"{"table": "order_info", "order_id": 7, "action_type": "UPDATE"}"#;
+        assert_eq!(OrderEvent::Updated(7), parse(payload).expect("must be ok"));
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        assert!(parse("not json").is_err());
+        assert!(parse(r#"{"table": "order_info", "action_type": "TRUNCATE"}"#).is_err());
+        assert!(parse(r#"{"table": "order_info", "action_type": "DELETE"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_channel_source_batches() {
+        let (tx, channel) = OrderEventChannel::new(8);
+        let mut source = ChannelSource::new(channel.clone()).await;
+        tx.send(OrderEvent::Created(1)).await.expect("must be ok");
+        tx.send(OrderEvent::Deleted(1)).await.expect("must be ok");
+
+        let events = source.next_events().await.expect("must be ok");
+        assert_eq!(vec![OrderEvent::Created(1), OrderEvent::Deleted(1)], events);
+
+        // a restarted task takes the receiver over once the old source is gone
+        drop(source);
+        let mut source = ChannelSource::new(channel).await;
+        tx.send(OrderEvent::Updated(2)).await.expect("must be ok");
+        assert_eq!(vec![OrderEvent::Updated(2)], source.next_events().await.expect("must be ok"));
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::PgListener;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use lib_dto::order::OrderStatus;
+
+use crate::bmc::order::OrderBmc;
+use crate::context::app_context::ModelManager;
+use crate::notify::source::{parse, OrderEvent, OrderEventSource, EVENT_BATCH};
+use crate::task::actor::BoxFuture;
+use crate::task::supervisor::Backoff;
+
+const CHANNELS: [&str; 1] = ["table_update"];
+
+/// `LISTEN`s to the `order_info` triggers.
+///
+/// A lost connection is reopened with backoff, then the orders changed since the last seen
+/// `updated_at` are handed out as if they were notified, so no order is missed across a reconnect.
+/// The same catch-up runs when `NotifyTask` itself was restarted.
+pub(crate) struct PgListenerSource {
+    app_context: Arc<ModelManager>,
+    connection: Connection,
+    backoff: Backoff,
+    // stays set until a catch-up query completed, so a cancelled one is run again
+    catch_up: bool,
+}
+
+enum Connection {
+    Connected(PgListener),
+    Lost { attempt: u32, retry_at: Instant },
+    Closed,
+}
+
+impl PgListenerSource {
+    pub(crate) async fn connect(app_context: Arc<ModelManager>) -> Result<Self> {
+        let listener = listen(&app_context).await?;
+        let stats = app_context.listener_stats();
+        // a previous incarnation saw orders, so this one starts with a catch-up
+        let restarted = stats.last_seen().is_some();
+        if restarted {
+            stats.reconnected();
+        } else {
+            // orders before the latest one are left to RecoveryTask
+            let latest = OrderBmc::latest_update(&app_context).await?;
+            stats.seen(latest.unwrap_or(DateTime::<Utc>::UNIX_EPOCH));
+            stats.connected();
+        }
+
+        Ok(Self {
+            app_context,
+            connection: Connection::Connected(listener),
+            backoff: Backoff::new(Duration::from_millis(100), Duration::from_secs(30)),
+            catch_up: restarted,
+        })
+    }
+
+    async fn next(&mut self) -> Result<Vec<OrderEvent>> {
+        loop {
+            if self.catch_up {
+                let events = self.caught_up().await?;
+                self.catch_up = false;
+                if !events.is_empty() {
+                    return Ok(events);
+                }
+            }
+            match &mut self.connection {
+                Connection::Connected(listener) => match listener.try_recv().await {
+                    Ok(Some(notification)) => {
+                        let mut notifications = vec![notification];
+                        while notifications.len() < EVENT_BATCH {
+                            let Some(notification) = listener.next_buffered() else {
+                                break;
+                            };
+                            notifications.push(notification);
+                        }
+                        let events: Vec<OrderEvent> = notifications.iter()
+                            .filter_map(|notification| match parse(notification.payload()) {
+                                Ok(event) => Some(event),
+                                Err(e) => {
+                                    error!("Skipping malformed notification {}: {:#?}", notification.payload(), e);
+                                    None
+                                }
+                            })
+                            .collect();
+                        if !events.is_empty() {
+                            return Ok(events);
+                        }
+                    }
+                    // the listener would silently reconnect on the next call, the reconnect is done here
+                    // instead, so it is backed off and followed by a catch-up
+                    Ok(None) => self.lost("connection closed".to_string()),
+                    Err(e) => self.lost(e.to_string()),
+                },
+                Connection::Lost { attempt, retry_at } => {
+                    tokio::time::sleep_until(*retry_at).await;
+                    let attempt = *attempt;
+                    match listen(&self.app_context).await {
+                        Ok(listener) => {
+                            self.app_context.listener_stats().reconnected();
+                            info!("PgListener reconnected after {} failed attempts", attempt);
+                            self.connection = Connection::Connected(listener);
+                            self.catch_up = true;
+                        }
+                        Err(e) => {
+                            warn!("Failed to reconnect PgListener: {:#}", e);
+                            self.app_context.listener_stats().disconnected(Some(e.to_string()));
+                            self.connection = Connection::Lost {
+                                attempt: attempt + 1,
+                                retry_at: Instant::now() + self.backoff.delay(attempt + 1),
+                            };
+                        }
+                    }
+                }
+                Connection::Closed => return std::future::pending().await,
+            }
+        }
+    }
+
+    fn lost(&mut self, error: String) {
+        warn!("PgListener lost its connection: {}", error);
+        self.app_context.listener_stats().disconnected(Some(error));
+        self.connection = Connection::Lost { attempt: 0, retry_at: Instant::now() + self.backoff.delay(0) };
+    }
+
+    /// The orders changed since the last seen one, a deletion can't be caught up.
+    async fn caught_up(&self) -> Result<Vec<OrderEvent>> {
+        let Some(since) = self.app_context.listener_stats().last_seen() else {
+            return Ok(vec![]);
+        };
+        // the last seen change is handed out again, a change in the same instant may have been missed
+        let orders = OrderBmc::get_updated_since(&self.app_context, since).await?;
+        info!("Catching up {} orders changed since {}", orders.len(), since);
+        let events = orders.iter()
+            .map(|order| match order.status() {
+                OrderStatus::New => OrderEvent::Created(order.order_id()),
+                _ => OrderEvent::Updated(order.order_id()),
+            })
+            .collect();
+        Ok(events)
+    }
+}
+
+impl OrderEventSource for PgListenerSource {
+    fn next_events(&mut self) -> BoxFuture<'_, Result<Vec<OrderEvent>>> {
+        Box::pin(self.next())
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if let Connection::Connected(mut listener) = std::mem::replace(&mut self.connection, Connection::Closed) {
+                info!("Stopping PgListener");
+                if let Err(e) = listener.unlisten_all().await {
+                    error!("Failed to unlisten: {:#?}", e)
+                }
+            }
+            self.app_context.listener_stats().disconnected(None);
+        })
+    }
+}
+
+impl Drop for PgListenerSource {
+    fn drop(&mut self) {
+        self.app_context.listener_stats().disconnected(None);
+    }
+}
+
+async fn listen(app_context: &ModelManager) -> Result<PgListener> {
+    let mut listener = PgListener::connect_with(app_context.pg_pool()).await?;
+    listener.listen_all(CHANNELS).await?;
+    Ok(listener)
+}
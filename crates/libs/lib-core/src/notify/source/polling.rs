@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::task::JoinHandle;
+use tokio::time::{Interval, MissedTickBehavior};
+use tracing::info;
+
+use crate::bmc::order::OrderBmc;
+use crate::context::app_context::ModelManager;
+use crate::notify::source::{OrderEvent, OrderEventSource, EVENT_BATCH};
+use crate::task::actor::BoxFuture;
+
+/// Polls for new orders, for connections which can't `LISTEN`.
+///
+/// A polled order is claimed by moving it to `InProgress` in the polling transaction,
+/// so several instances never take the same order. An order claimed by an instance which
+/// stops before finishing it is resumed by `RecoveryTask`.
+pub(crate) struct PollingSource {
+    app_context: Arc<ModelManager>,
+    // None once closed
+    interval: Option<Interval>,
+    // the claim commits in its own task, so a cancelled `next_events` picks its result up next time
+    claim: Option<JoinHandle<crate::error::Result<Vec<i64>>>>,
+}
+
+impl PollingSource {
+    pub(crate) const ACTOR: &'static str = "OrderPoller";
+
+    pub(crate) fn new(app_context: Arc<ModelManager>, poll_interval: Duration) -> Self {
+        let mut interval = tokio::time::interval(poll_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self { app_context, interval: Some(interval), claim: None }
+    }
+
+    async fn next(&mut self) -> Result<Vec<OrderEvent>> {
+        loop {
+            if self.claim.is_none() {
+                let Some(interval) = self.interval.as_mut() else {
+                    return std::future::pending().await;
+                };
+                interval.tick().await;
+                let app_context = self.app_context.clone();
+                self.claim = Some(tokio::spawn(async move {
+                    OrderBmc::claim_new(&app_context, EVENT_BATCH as i64, Self::ACTOR).await
+                }));
+            }
+            let Some(claim) = self.claim.as_mut() else {
+                continue;
+            };
+            let claimed = claim.await;
+            self.claim = None;
+            let order_ids = claimed??;
+            if !order_ids.is_empty() {
+                info!("Claimed {} new orders", order_ids.len());
+                return Ok(order_ids.into_iter().map(OrderEvent::Created).collect());
+            }
+        }
+    }
+}
+
+impl OrderEventSource for PollingSource {
+    fn next_events(&mut self) -> BoxFuture<'_, Result<Vec<OrderEvent>>> {
+        Box::pin(self.next())
+    }
+
+    fn close(&mut self) -> BoxFuture<'_, ()> {
+        self.interval = None;
+        Box::pin(async {})
+    }
+}
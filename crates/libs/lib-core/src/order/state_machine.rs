@@ -0,0 +1,81 @@
+use lib_dto::order::OrderStatus;
+
+use crate::error::{Error, Result};
+
+/// Allowed moves of an order:
+///
+/// `New -> InProgress -> ReadyToDeliver -> Delivered`, any non terminal
+/// status may end up `Cancelled` or `Failed`, and a failed order may be requeued as `New`.
+pub fn can_transition(from: &OrderStatus, to: &OrderStatus) -> bool {
+    use OrderStatus::*;
+
+    matches!(
+        (from, to),
+        (New, InProgress)
+            | (InProgress, ReadyToDeliver)
+            | (ReadyToDeliver, Delivered)
+            | (New | InProgress | ReadyToDeliver, Cancelled | Failed)
+            | (Failed, New)
+    )
+}
+
+/// The pipeline is done with an order in a terminal status, only an admin requeues a failed one.
+pub fn is_terminal(status: &OrderStatus) -> bool {
+    matches!(status, OrderStatus::Delivered | OrderStatus::Cancelled | OrderStatus::Failed)
+}
+
+pub fn ensure_transition(order_id: i64, from: &OrderStatus, to: &OrderStatus) -> Result<()> {
+    if can_transition(from, to) {
+        Ok(())
+    } else {
+        Err(Error::IllegalTransition { order_id, from: from.clone(), to: to.clone() })
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use OrderStatus::*;
+
+    use super::*;
+
+    const ALL: [OrderStatus; 6] = [New, InProgress, ReadyToDeliver, Delivered, Cancelled, Failed];
+
+    #[test]
+    fn test_transitions() {
+        let allowed = [
+            (New, InProgress),
+            (InProgress, ReadyToDeliver),
+            (ReadyToDeliver, Delivered),
+            (New, Cancelled),
+            (InProgress, Cancelled),
+            (ReadyToDeliver, Cancelled),
+            (New, Failed),
+            (InProgress, Failed),
+            (ReadyToDeliver, Failed),
+            (Failed, New),
+        ];
+        for from in ALL.iter() {
+            for to in ALL.iter() {
+                let expected = allowed.contains(&(from.clone(), to.clone()));
+                assert_eq!(expected, can_transition(from, to), "{:?} -> {:?}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn test_terminal_statuses_have_no_way_out() {
+        for from in ALL.iter().filter(|status| is_terminal(status)) {
+            for to in ALL.iter().filter(|to| (from, *to) != (&Failed, &New)) {
+                assert!(ensure_transition(1, from, to).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_illegal_transition_is_typed() {
+        let e = ensure_transition(7, &New, &Delivered).unwrap_err();
+        assert!(matches!(e, Error::IllegalTransition { order_id: 7, from: New, to: Delivered }));
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,472 @@
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::select;
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::context::app_context::ModelManager;
+use crate::task::supervisor::{Backoff, RestartPolicy, SharedReceiver, TaskSpec};
+use crate::task::worker_pool::WorkerPoolMetrics;
+
+pub const DEFAULT_MAILBOX_CAPACITY: usize = 64;
+
+/// A long-running task with a typed mailbox.
+///
+/// The runtime in this module owns the mailbox loop, so an implementation only describes how to
+/// build itself and how to handle its own requests. Health checks, draining and restarts are the
+/// same for every actor and are answered by the runtime through the hooks below.
+pub trait Actor: Sized + Send + 'static {
+    /// Requests sent through `ActorHandle::call` / `ActorHandle::cast`.
+    /// A request expecting an answer carries its own `oneshot::Sender`.
+    type Request: Debug + Send + 'static;
+    /// Events produced by `next_event`, for actors driven by an external source.
+    type Event: Debug + Send + 'static;
+
+    const NAME: &'static str;
+    const MAILBOX_CAPACITY: usize = DEFAULT_MAILBOX_CAPACITY;
+
+    fn task_spec() -> TaskSpec {
+        TaskSpec::new(Self::NAME, RestartPolicy::Always, Backoff::default())
+    }
+
+    /// Builds a fresh instance, called on every (re)start.
+    fn create(app_context: Arc<ModelManager>) -> impl Future<Output = Result<Self>> + Send;
+
+    fn handle(&mut self, request: Self::Request) -> impl Future<Output = Result<()>> + Send;
+
+    /// Waits for the next external event, must be cancel safe.
+    fn next_event(&mut self) -> impl Future<Output = Result<Self::Event>> + Send {
+        std::future::pending()
+    }
+
+    fn handle_event(&mut self, event: Self::Event) -> impl Future<Output = Result<()>> + Send {
+        async move {
+            info!("{} ignores event {:?}", Self::NAME, event);
+            Ok(())
+        }
+    }
+
+    fn started(&mut self) -> impl Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Answers the built-in health request, the actor is healthy as long as it answers.
+    fn health(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Answers the built-in report request with what the actor is currently working on.
+    fn report(&mut self) -> ActorReport {
+        ActorReport::default()
+    }
+
+    /// Called when a drain request reaches the front of the mailbox, i.e. after every request
+    /// queued before it was handled. Actors with work in flight wait for it here.
+    fn drain(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
+    fn stopped(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ActorReport {
+    /// Ids of the orders the actor is currently working on.
+    pub in_flight_orders: Vec<i64>,
+    pub worker_pool: Option<WorkerPoolMetrics>,
+}
+
+pub(crate) enum Envelope<Req> {
+    Request(Req),
+    Health(oneshot::Sender<Result<()>>),
+    Report(oneshot::Sender<ActorReport>),
+    Drain(oneshot::Sender<()>),
+}
+
+impl<Req: Debug> Debug for Envelope<Req> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Envelope::Request(request) => write!(f, "Request({:?})", request),
+            Envelope::Health(_) => f.write_str("Health"),
+            Envelope::Report(_) => f.write_str("Report"),
+            Envelope::Drain(_) => f.write_str("Drain"),
+        }
+    }
+}
+
+/// Cloneable address of an actor, it stays valid across restarts of the actor.
+pub struct ActorHandle<Req> {
+    name: &'static str,
+    tx: Sender<Envelope<Req>>,
+}
+
+impl<Req> Clone for ActorHandle<Req> {
+    fn clone(&self) -> Self {
+        Self { name: self.name, tx: self.tx.clone() }
+    }
+}
+
+impl<Req> Debug for ActorHandle<Req> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ActorHandle({})", self.name)
+    }
+}
+
+impl<Req: Send + 'static> ActorHandle<Req> {
+    pub(crate) fn new(name: &'static str, tx: Sender<Envelope<Req>>) -> Self {
+        Self { name, tx }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Sends a request without waiting for an answer.
+    pub async fn cast(&self, request: Req) -> Result<()> {
+        self.tx.send(Envelope::Request(request)).await
+            .map_err(|_| anyhow!("{} mailbox is closed", self.name))
+    }
+
+    /// Sends the request built by `request` and waits up to `timeout` for the answer.
+    pub async fn call<Resp>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Resp>) -> Req,
+        timeout: Duration,
+    ) -> Result<Resp> {
+        let (tx, rx) = oneshot::channel();
+        tokio::time::timeout(timeout, async {
+            self.cast(request(tx)).await?;
+            rx.await.map_err(|_| anyhow!("{} dropped the request", self.name))
+        }).await
+            .map_err(|_| anyhow!("{} did not answer within {:?}", self.name, timeout))?
+    }
+
+    pub async fn health(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(Envelope::Health(tx)).await
+            .map_err(|_| anyhow!("{} mailbox is closed", self.name))?;
+        rx.await.map_err(|_| anyhow!("{} dropped the health request", self.name))?
+    }
+
+    pub async fn report(&self) -> Result<ActorReport> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(Envelope::Report(tx)).await
+            .map_err(|_| anyhow!("{} mailbox is closed", self.name))?;
+        rx.await.map_err(|_| anyhow!("{} dropped the report request", self.name))
+    }
+
+    /// Resolves once every request queued before the call has been handled.
+    pub async fn drain(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.tx.send(Envelope::Drain(tx)).await
+            .map_err(|_| anyhow!("{} mailbox is closed", self.name))?;
+        rx.await.map_err(|_| anyhow!("{} dropped the drain request", self.name))
+    }
+
+    pub fn mailbox_depth(&self) -> usize {
+        self.tx.max_capacity() - self.tx.capacity()
+    }
+
+    pub fn mailbox_capacity(&self) -> usize {
+        self.tx.max_capacity()
+    }
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Type-erased view of an `ActorHandle`, so `TaskManager` can keep handles of all actors together.
+pub(crate) trait AnyActorHandle: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn mailbox_depth(&self) -> usize;
+    fn mailbox_capacity(&self) -> usize;
+    fn health(&self) -> BoxFuture<'_, Result<()>>;
+    fn report(&self) -> BoxFuture<'_, Result<ActorReport>>;
+    fn drain(&self) -> BoxFuture<'_, Result<()>>;
+    fn clone_any(&self) -> Box<dyn Any + Send>;
+}
+
+impl<Req: Send + 'static> AnyActorHandle for ActorHandle<Req> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn mailbox_depth(&self) -> usize {
+        ActorHandle::mailbox_depth(self)
+    }
+
+    fn mailbox_capacity(&self) -> usize {
+        ActorHandle::mailbox_capacity(self)
+    }
+
+    fn health(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(ActorHandle::health(self))
+    }
+
+    fn report(&self) -> BoxFuture<'_, Result<ActorReport>> {
+        Box::pin(ActorHandle::report(self))
+    }
+
+    fn drain(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(ActorHandle::drain(self))
+    }
+
+    fn clone_any(&self) -> Box<dyn Any + Send> {
+        Box::new(self.clone())
+    }
+}
+
+/// Spawns one incarnation of `A` reading from `rx`, the supervisor calls this on every restart.
+pub(crate) fn start<A: Actor>(
+    app_context: Arc<ModelManager>,
+    rx: SharedReceiver<Envelope<A::Request>>,
+) -> JoinHandle<Result<()>> {
+    let cancellation_token = app_context.cancellation_token();
+    tokio::spawn(async move {
+        select! {
+            res = run::<A>(app_context, rx) => res,
+            _ = cancellation_token.cancelled() => {
+                info!("{} cancelled by cancellation token.", A::NAME);
+                Ok(())
+            }
+        }
+    })
+}
+
+async fn run<A: Actor>(
+    app_context: Arc<ModelManager>,
+    rx: SharedReceiver<Envelope<A::Request>>,
+) -> Result<()> {
+    let mut rx = rx.lock().await;
+    let mut actor = A::create(app_context).await?;
+    actor.started().await?;
+    info!("{} started", A::NAME);
+
+    let result = loop {
+        select! {
+            envelope = rx.recv() => {
+                let Some(envelope) = envelope else {
+                    break Ok(());
+                };
+                match envelope {
+                    Envelope::Request(request) => {
+                        if let Err(e) = actor.handle(request).await {
+                            break Err(e);
+                        }
+                    }
+                    Envelope::Health(tx) => {
+                        if tx.send(actor.health()).is_err() {
+                            error!("{} failed to send health", A::NAME)
+                        }
+                    }
+                    Envelope::Report(tx) => {
+                        if tx.send(actor.report()).is_err() {
+                            error!("{} failed to send report", A::NAME)
+                        }
+                    }
+                    Envelope::Drain(tx) => {
+                        actor.drain().await;
+                        info!("{} drained", A::NAME);
+                        if tx.send(()).is_err() {
+                            error!("{} failed to send drained", A::NAME)
+                        }
+                    }
+                }
+            }
+            event = actor.next_event() => {
+                let handled = match event {
+                    Ok(event) => actor.handle_event(event).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = handled {
+                    break Err(e);
+                }
+            }
+        }
+    };
+
+    actor.stopped().await;
+    info!("{} stopped", A::NAME);
+    result
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use sqlx::postgres::PgPoolOptions;
+
+    use crate::bus::MessageBusConfig;
+    use crate::context::app_context::AppConfig;
+    use crate::task::supervisor::{shared_channel, supervise, TaskStats};
+
+    use super::*;
+
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+    static STOPPED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Debug)]
+    enum EchoRequest {
+        Echo(u32, oneshot::Sender<u32>),
+        Seen(oneshot::Sender<(Vec<u32>, bool)>),
+        Fail,
+        Panic,
+    }
+
+    /// Echoes requests and handles one event right after it started, numbered by the incarnation.
+    struct EchoActor {
+        event: Option<u32>,
+        events: Vec<u32>,
+        drained: bool,
+        /// `Seen` arriving before the event is answered once it was handled.
+        seen_tx: Option<oneshot::Sender<(Vec<u32>, bool)>>,
+    }
+
+    impl EchoActor {
+        fn answer_seen(&mut self) {
+            if self.event.is_none() {
+                if let Some(tx) = self.seen_tx.take() {
+                    let _ = tx.send((self.events.clone(), self.drained));
+                }
+            }
+        }
+    }
+
+    impl Actor for EchoActor {
+        type Request = EchoRequest;
+        type Event = u32;
+
+        const NAME: &'static str = "EchoActor";
+
+        fn task_spec() -> TaskSpec {
+            TaskSpec::new(Self::NAME, RestartPolicy::Always, Backoff::new(Duration::from_millis(1), Duration::from_millis(5)))
+        }
+
+        async fn create(_app_context: Arc<ModelManager>) -> Result<Self> {
+            Ok(EchoActor { event: None, events: vec![], drained: false, seen_tx: None })
+        }
+
+        async fn handle(&mut self, request: EchoRequest) -> Result<()> {
+            match request {
+                EchoRequest::Echo(value, tx) => {
+                    let _ = tx.send(value);
+                    Ok(())
+                }
+                EchoRequest::Seen(tx) => {
+                    self.seen_tx = Some(tx);
+                    self.answer_seen();
+                    Ok(())
+                }
+                EchoRequest::Fail => Err(anyhow!("asked to fail")),
+                EchoRequest::Panic => panic!("asked to panic"),
+            }
+        }
+
+        async fn next_event(&mut self) -> Result<u32> {
+            match self.event {
+                Some(event) => Ok(event),
+                None => std::future::pending().await,
+            }
+        }
+
+        async fn handle_event(&mut self, event: u32) -> Result<()> {
+            self.event = None;
+            self.events.push(event);
+            self.answer_seen();
+            Ok(())
+        }
+
+        async fn started(&mut self) -> Result<()> {
+            let started = STARTED.fetch_add(1, Ordering::SeqCst) as u32 + 1;
+            self.event = Some(started);
+            Ok(())
+        }
+
+        async fn drain(&mut self) {
+            self.drained = true;
+        }
+
+        async fn stopped(&mut self) {
+            STOPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// The pool connects lazily and the bus is in memory, so nothing needs to run besides the test.
+    fn app_context() -> Arc<ModelManager> {
+        let (main_tx, _) = tokio::sync::mpsc::channel(1);
+        let app_config = AppConfig {
+            auth_url: Arc::new(String::new()),
+            kafka_url: Arc::new(String::new()),
+            shutdown_deadline: Duration::from_secs(1),
+            order_workers: 1,
+            admin_phones: Arc::default(),
+            update_retry: Default::default(),
+            stale_order_after: Duration::from_secs(60),
+            order_source: Default::default(),
+            kafka_producer: Default::default(),
+            kafka_topics: Default::default(),
+            message_bus: MessageBusConfig::InMemory { partitions: 1 },
+            consumer_offsets: Default::default(),
+            dead_letter: Default::default(),
+        };
+        let pg_pool = PgPoolOptions::new().connect_lazy("postgres://localhost/unused").expect("must be ok");
+        Arc::new(ModelManager::create(main_tx, app_config, Arc::new(pg_pool)))
+    }
+
+    async fn echo(handle: &ActorHandle<EchoRequest>, value: u32) -> Result<u32> {
+        handle.call(|tx| EchoRequest::Echo(value, tx), Duration::from_secs(1)).await
+    }
+
+    async fn seen(handle: &ActorHandle<EchoRequest>) -> (Vec<u32>, bool) {
+        handle.call(EchoRequest::Seen, Duration::from_secs(1)).await.expect("must be ok")
+    }
+
+    #[tokio::test]
+    async fn test_call_health_and_restart() {
+        let app_context = app_context();
+        let (tx, rx) = shared_channel(4);
+        let handle = ActorHandle::new(EchoActor::NAME, tx);
+        let stats = Arc::new(TaskStats::default());
+        let app_context_cloned = app_context.clone();
+        let supervisor = supervise(EchoActor::task_spec(), stats.clone(), app_context.cancellation_token(), move || {
+            start::<EchoActor>(app_context_cloned.clone(), rx.clone())
+        });
+
+        assert_eq!(7, echo(&handle, 7).await.expect("must be ok"));
+        handle.health().await.expect("must be ok");
+        assert!(handle.report().await.expect("must be ok").in_flight_orders.is_empty());
+        handle.cast(EchoRequest::Echo(8, oneshot::channel().0)).await.expect("must be ok");
+        handle.drain().await.expect("must be ok");
+        assert_eq!((vec![1], true), seen(&handle).await);
+        assert_eq!(0, handle.mailbox_depth());
+        assert_eq!(4, handle.mailbox_capacity());
+
+        // a failed request stops the actor, the supervisor starts a fresh one on the same mailbox
+        handle.cast(EchoRequest::Fail).await.expect("must be ok");
+        assert_eq!(9, echo(&handle, 9).await.expect("must be ok"));
+        assert_eq!((vec![2], false), seen(&handle).await);
+        assert_eq!(1, STOPPED.load(Ordering::SeqCst));
+
+        // a panic skips the stopped hook but is restarted all the same
+        handle.cast(EchoRequest::Panic).await.expect("must be ok");
+        assert_eq!(10, echo(&handle, 10).await.expect("must be ok"));
+        assert_eq!((vec![3], false), seen(&handle).await);
+        assert_eq!(1, STOPPED.load(Ordering::SeqCst));
+        assert_eq!(2, stats.restarts());
+
+        app_context.cancellation_token().cancel();
+        supervisor.await.expect("must be ok");
+        assert!(echo(&handle, 11).await.is_err());
+    }
+}
+// endregion: --- Tests
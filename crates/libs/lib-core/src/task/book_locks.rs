@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::OwnedMutexGuard;
+
+/// Keyed locks serializing the orders which touch the same books, so their
+/// `REPEATABLE READ` transactions don't keep failing on each other.
+///
+/// There is one lock per book id ever seen, the catalog is small enough to keep them all.
+#[derive(Clone, Default)]
+pub(crate) struct BookLocks {
+    locks: Arc<Mutex<HashMap<i64, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+/// Holds the locks of an order's books until dropped.
+pub(crate) struct BookGuard {
+    _guards: Vec<OwnedMutexGuard<()>>,
+}
+
+impl BookLocks {
+    /// Locks every book, always in ascending id order so that two orders can't deadlock.
+    /// The flag tells whether another order held one of the books.
+    pub(crate) async fn lock(&self, book_ids: impl IntoIterator<Item = i64>) -> (BookGuard, bool) {
+        let mut book_ids: Vec<i64> = book_ids.into_iter().collect();
+        book_ids.sort_unstable();
+        book_ids.dedup();
+
+        let book_locks: Vec<Arc<tokio::sync::Mutex<()>>> = {
+            let mut locks = self.locks.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            book_ids.iter()
+                .map(|book_id| locks.entry(*book_id).or_default().clone())
+                .collect()
+        };
+
+        let mut contended = false;
+        let mut guards = Vec::with_capacity(book_locks.len());
+        for book_lock in book_locks {
+            let guard = match book_lock.clone().try_lock_owned() {
+                Ok(guard) => guard,
+                Err(_) => {
+                    contended = true;
+                    book_lock.lock_owned().await
+                }
+            };
+            guards.push(guard);
+        }
+
+        (BookGuard { _guards: guards }, contended)
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shared_books_are_serialized() {
+        let book_locks = BookLocks::default();
+
+        let (guard, contended) = book_locks.lock([2, 1, 2]).await;
+        assert!(!contended);
+
+        // disjoint books don't wait
+        let (other, contended) = book_locks.lock([3]).await;
+        assert!(!contended);
+
+        let book_locks_cloned = book_locks.clone();
+        let waiting = tokio::spawn(async move { book_locks_cloned.lock([1, 3]).await.1 });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        drop(guard);
+        drop(other);
+        assert!(waiting.await.unwrap());
+    }
+}
+// endregion: --- Tests
@@ -1,118 +1,121 @@
 use std::sync::Arc;
-use std::time::Duration;
 
 use anyhow::Result;
-use tokio::select;
-use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::oneshot;
-use tracing::{info, instrument};
+use tracing::{error, info, instrument, Instrument};
 
 use lib_dto::order::OrderStatus::Delivered;
 use lib_dto::order::OrderStored;
 
 use crate::bmc::general::update_storage_and_order;
-use crate::bmc::storage::UpdateType::Remove;
+use crate::bmc::storage::UpdateType::Consume;
 use crate::context::app_context::ModelManager;
-use crate::select_cancel;
-use crate::task::delivery::DeliveryResponse::HealthOk;
-use crate::task::kafka::producer_task::KafkaProducerRequest;
-use crate::task::main_task::{TaskManager};
+use crate::error::Error;
+use crate::task::actor::{Actor, ActorHandle, ActorReport};
+use crate::task::in_flight::InFlight;
+use crate::task::kafka::producer_task::{KafkaProducerRequest, KafkaProducerTask};
+use crate::task::main_task::TaskManager;
+use crate::task::retry::GaveUp;
+use crate::telemetry::order_span;
 
 #[derive(Debug)]
 pub enum DeliveryRequest {
-    Health(oneshot::Sender<DeliveryResponse>),
     Deliver(OrderStored, oneshot::Sender<DeliveryResponse>),
+    Abort(i64),
 }
 
 #[derive(Debug)]
 pub enum DeliveryResponse {
-    HealthOk,
     Delivered,
+    /// The order was already moved on, by a cancellation or another submission.
+    Skipped,
 
     // contains order_id
-    FailedToDeliver(i64)
+    FailedToDeliver(i64),
 }
 
-pub(crate) struct DeliveryTask;
+pub(crate) struct DeliveryTask {
+    app_context: Arc<ModelManager>,
+    kafka_producer: ActorHandle<KafkaProducerRequest>,
+    // in-flight deliveries are tracked instead of racing the cancellation token,
+    // so a shutdown can wait for their transactions to finish
+    in_flight: InFlight,
+}
 
-impl DeliveryTask {
-    pub(crate) fn start(
-        app_context: Arc<ModelManager>,
-    ) -> Sender<DeliveryRequest> {
-        let (tx, rx) = tokio::sync::mpsc::channel(64);
+impl Actor for DeliveryTask {
+    type Request = DeliveryRequest;
+    type Event = ();
 
-        let cancellation_token = app_context.cancellation_token();
-        tokio::spawn(async move {
-            select! {
-                _ = handle_delivery_requests(app_context, rx) => {}
-                _ = cancellation_token.cancelled() => {
-                    info!("Cancelled by cancellation token.")
-                }
-            }
-        });
+    const NAME: &'static str = "DeliveryTask";
 
-        tx.clone()
+    async fn create(app_context: Arc<ModelManager>) -> Result<Self> {
+        let kafka_producer = TaskManager::actor::<KafkaProducerTask>(app_context.main_tx()).await?;
+        Ok(Self { app_context, kafka_producer, in_flight: InFlight::default() })
     }
-}
 
-#[instrument(skip_all)]
-pub async fn handle_delivery_requests(
-    app_context: Arc<ModelManager>,
-    mut delivery_rx: Receiver<DeliveryRequest>,
-) -> Result<()> {
-    info!("Starting handle delivery task");
-    let main_tx = app_context.main_tx();
-    let kafka_tx = TaskManager::kafka_producer_sender(main_tx.clone()).await?;
-    info!("Got kafka_tx");
-
-    let cancellation_token = app_context.cancellation_token();
-
-    while let Some(request) = delivery_rx.recv().await {
+    #[instrument(skip_all)]
+    async fn handle(&mut self, request: DeliveryRequest) -> Result<()> {
         info!("Got delivery request: {:#?}", &request);
-
-        let cancellation_token_cloned = cancellation_token.clone();
-        let app_context_cloned = app_context.clone();
+        self.in_flight.reap();
 
         match request {
-            DeliveryRequest::Health(tx) => {
-                tx.send(HealthOk).expect("TODO: panic message")
-            }
             DeliveryRequest::Deliver(order, tx) => {
-                info!("Sending kafka request: {:#?}", &order.order_id());
-                kafka_tx.send(KafkaProducerRequest::ProduceOrder(order.clone())).await.expect("must be ok");
-                select_cancel!(handle_order(app_context_cloned, order, tx), cancellation_token_cloned);
+                let span = order_span(Self::NAME, &order);
+                let order_id = order.order_id();
+                let kafka_producer = self.kafka_producer.clone();
+                // created in the order span, so the consumers of the event continue the trace from the delivery
+                let delivery = span.in_scope(|| handle_order(self.app_context.clone(), kafka_producer, order, tx));
+                self.in_flight.spawn(order_id, delivery.instrument(span));
+            }
+            DeliveryRequest::Abort(order_id) => {
+                if self.in_flight.abort(order_id) {
+                    info!("Aborted delivery of order {}", order_id);
+                }
             }
         }
+
+        Ok(())
     }
 
-    Ok(())
+    fn report(&mut self) -> ActorReport {
+        self.in_flight.reap();
+        ActorReport { in_flight_orders: self.in_flight.order_ids(), ..Default::default() }
+    }
+
+    async fn drain(&mut self) {
+        self.in_flight.join_all().await;
+    }
 }
 
 #[instrument(skip_all)]
 pub async fn handle_order(
     app_context: Arc<ModelManager>,
+    kafka_producer: ActorHandle<KafkaProducerRequest>,
     order: OrderStored,
     response_tx: oneshot::Sender<DeliveryResponse>
 ) {
     let order_id = order.order_id();
     info!("delivering order: {:#?}", &order_id);
-    select! {
-        // todo think about cancellation safety here
-        _ = update_with_retry(app_context, &order) => {
-            response_tx.send(DeliveryResponse::Delivered).expect("TODO: panic message");
+    let retry = &app_context.app_config().update_retry;
+    let delivered = retry.run(|| {
+        update_storage_and_order(&app_context, &order, Consume, Delivered, DeliveryTask::NAME, None, true)
+    }).await;
+    let response = match delivered {
+        Ok(()) => DeliveryResponse::Delivered,
+        Err(GaveUp { error: Error::IllegalTransition { .. }, .. }) => DeliveryResponse::Skipped,
+        Err(gave_up) => {
+            error!("Order {} was not delivered: {}", order_id, gave_up.error);
+            gave_up.settle(&app_context, order_id, DeliveryTask::NAME).await;
+            DeliveryResponse::FailedToDeliver(order_id)
         }
-        _ = tokio::time::sleep(Duration::from_secs(3)) => {
-            response_tx.send(DeliveryResponse::FailedToDeliver(order_id)).expect("TODO: panic message");
+    };
+    if let DeliveryResponse::Delivered = response {
+        // the relay polls the outbox anyway, this only saves the wait
+        if let Err(e) = kafka_producer.cast(KafkaProducerRequest::Relay(None)).await {
+            error!("Failed to wake kafka producer: {:#?}", e)
         }
     }
-}
-
-#[instrument(skip_all)]
-async fn update_with_retry(
-    app_context: Arc<ModelManager>,
-    order: &OrderStored,
-)  {
-    while let Err(e) = update_storage_and_order(app_context.clone(), &order, Remove, Delivered).await {
-        info!("delivery retrying update storage for order is: {:#?} because of {:#?}", &order, e);
+    if response_tx.send(response).is_err() {
+        error!("failed to send delivery response")
     }
-}
\ No newline at end of file
+}
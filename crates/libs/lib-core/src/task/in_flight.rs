@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use tokio::task::{AbortHandle, Id, JoinSet};
+use tracing::{error, info};
+
+/// Order work spawned by a pipeline stage, tracked so it can be listed and awaited on drain.
+#[derive(Default)]
+pub(crate) struct InFlight {
+    tasks: JoinSet<()>,
+    orders: HashMap<Id, (i64, AbortHandle)>,
+}
+
+impl InFlight {
+    pub(crate) fn spawn<F>(&mut self, order_id: i64, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let abort_handle = self.tasks.spawn(future);
+        self.orders.insert(abort_handle.id(), (order_id, abort_handle));
+    }
+
+    /// Forgets the work which already finished, without waiting.
+    pub(crate) fn reap(&mut self) {
+        while let Some(result) = self.tasks.try_join_next_with_id() {
+            self.finished(result);
+        }
+    }
+
+    pub(crate) async fn join_all(&mut self) {
+        while let Some(result) = self.tasks.join_next_with_id().await {
+            self.finished(result);
+        }
+    }
+
+    pub(crate) fn contains(&self, order_id: i64) -> bool {
+        self.orders.values().any(|(id, _)| *id == order_id)
+    }
+
+    /// Aborts the work of the order, an open transaction is rolled back when its task is dropped.
+    pub(crate) fn abort(&self, order_id: i64) -> bool {
+        let mut aborted = false;
+        for (_, abort_handle) in self.orders.values().filter(|(id, _)| *id == order_id) {
+            abort_handle.abort();
+            aborted = true;
+        }
+        aborted
+    }
+
+    pub(crate) fn order_ids(&self) -> Vec<i64> {
+        let mut order_ids: Vec<i64> = self.orders.values().map(|(id, _)| *id).collect();
+        order_ids.sort_unstable();
+        order_ids
+    }
+
+    fn finished(&mut self, result: Result<(Id, ()), tokio::task::JoinError>) {
+        let id = match result {
+            Ok((id, ())) => id,
+            Err(e) if e.is_cancelled() => {
+                info!("In-flight order task was aborted");
+                e.id()
+            }
+            Err(e) => {
+                error!("In-flight order task failed: {:#?}", e);
+                e.id()
+            }
+        };
+        self.orders.remove(&id);
+    }
+}
@@ -1,99 +1,318 @@
-use std::sync::Arc;
-use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
 use log::error;
-use rdkafka::{ClientConfig, Message};
-use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
-use tokio::select;
-use tokio::sync::oneshot;use tracing::{info, instrument};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinSet;
+use tracing::{info, info_span, instrument, warn, Instrument};
+
+use lib_dto::event::{OrderEvent, ORDER_EVENT_SCHEMA_VERSION};
 
-use crate::context::app_context::{AppConfig, ModelManager};
+use crate::bmc::consumer_offset::ConsumerOffsetBmc;
+use crate::bus::{BusConsumer, BusMessage, StartOffsets};
+use crate::context::app_context::ModelManager;
+use crate::task::actor::{Actor, BoxFuture};
+use crate::task::kafka::dead_letter::dead_letter_record;
+use crate::task::kafka::dispatcher::Dispatcher;
+use crate::task::kafka::{ConsumerOffsets, DeadLetterConfig};
+use crate::task::supervisor::Backoff;
+use crate::telemetry::{self, TRACEPARENT};
 
+/// Messages a partition may have queued before the consumer waits for it.
+const LANE_CAPACITY: usize = 64;
+const GROUP_ID: &str = "main-group";
+/// A partition retrying one message this many times makes the consumer unhealthy.
+const STUCK_AFTER_ATTEMPTS: u32 = 5;
+
+/// The consumer is driven by the topic, it takes no requests besides the built-in ones.
 #[derive(Debug)]
-pub enum KafkaConsumerRequest {
-    Health(oneshot::Sender<KafkaConsumerResponse>),
+pub enum KafkaConsumerRequest {}
+
+/// Dispatches the order events of `KafkaProducerConfig::topic` to the handlers of its `Dispatcher`.
+///
+/// Every partition has its own lane, which handles the messages one at a time in offset order,
+/// so events of an order (keyed by its id) are never reordered while partitions proceed independently.
+/// An offset is committed only after every handler succeeded, a failing message is retried with
+/// backoff and holds its partition back until it goes through or `DeadLetterConfig::max_attempts`
+/// are used up. Then it goes to `DeadLetterConfig::topic`, so does a malformed message
+/// and an event of a newer schema version than this build understands.
+///
+/// The handlers of an event share one transaction, which stores the offset as well under
+/// `ConsumerOffsets::Postgres`. A partition assigned to the consumer is sought to the stored offset then,
+/// and a message the group already processed is still skipped.
+///
+/// The handling of an event continues the trace of its `traceparent` header.
+pub(crate) struct KafkaConsumerTask {
+    handling: Handling,
+    lanes: HashMap<(String, i32), Lane>,
+    lane_tasks: JoinSet<()>,
+}
+
+/// What every lane needs to handle its messages.
+#[derive(Clone)]
+struct Handling {
+    app_context: Arc<ModelManager>,
+    consumer: Arc<dyn BusConsumer>,
+    dispatcher: Dispatcher,
+    offsets: ConsumerOffsets,
+    dead_letter: DeadLetterConfig,
+    backoff: Backoff,
+}
+
+struct Lane {
+    tx: Sender<BusMessage>,
+    state: Arc<LaneState>,
+}
+
+/// Written by the lane, read by the health check.
+#[derive(Debug, Default)]
+struct LaneState {
+    failing: Mutex<Option<Failing>>,
 }
 
 #[derive(Debug)]
-pub enum KafkaConsumerResponse {
-    HealthOk,
+struct Failing {
+    offset: i64,
+    attempts: u32,
+    error: String,
 }
 
-pub(crate) struct KafkaConsumerTask {
-    consumer: StreamConsumer,
+impl LaneState {
+    fn failing(&self) -> MutexGuard<'_, Option<Failing>> {
+        // the lock is never held across a panic point, recover the data anyway
+        self.failing.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 }
 
-impl KafkaConsumerTask {
-    #[instrument(skip_all)]
-    pub(crate) async fn start(
-        app_context: Arc<ModelManager>,
-    ) {
-        info!("Starting Kafka Consumer Task");
-
-        let app_config: AppConfig = app_context.app_config().clone();
-        let consumer = create(app_config, "main-group").await;
-        let task = {
-            Self { consumer }
+impl Actor for KafkaConsumerTask {
+    type Request = KafkaConsumerRequest;
+    type Event = BusMessage;
+
+    const NAME: &'static str = "KafkaConsumerTask";
+
+    async fn create(app_context: Arc<ModelManager>) -> Result<Self> {
+        let topic = app_context.app_config().kafka_producer.topic.as_str();
+        info!("Subscribing consumer to {}", topic);
+        let offsets = app_context.app_config().consumer_offsets;
+        let consumer = match offsets {
+            ConsumerOffsets::Kafka => app_context.message_bus().subscribe(&[topic], GROUP_ID).await?,
+            ConsumerOffsets::Postgres => {
+                let stored = Arc::new(StoredOffsets::new(app_context.clone(), GROUP_ID));
+                app_context.message_bus().subscribe_from(&[topic], GROUP_ID, stored).await?
+            }
         };
+        let dead_letter = app_context.app_config().dead_letter.clone();
+        Ok(Self {
+            handling: Handling {
+                app_context,
+                consumer: Arc::from(consumer),
+                dispatcher: Dispatcher::with_defaults(),
+                offsets,
+                dead_letter,
+                backoff: Backoff::new(Duration::from_millis(100), Duration::from_secs(30)),
+            },
+            lanes: HashMap::new(),
+            lane_tasks: JoinSet::new(),
+        })
+    }
+
+    async fn handle(&mut self, request: KafkaConsumerRequest) -> Result<()> {
+        match request {}
+    }
+
+    async fn next_event(&mut self) -> Result<BusMessage> {
+        self.handling.consumer.recv().await
+    }
 
-        let cancellation_token = app_context.cancellation_token();
-        let jh = tokio::spawn(async move {
-            select! {
-                _ = task.handle_kafka_consumer() => {}
-                _ = cancellation_token.cancelled() => {
-                    info!("Cancelled by cancellation token.")
+    /// Queues the message on the lane of its partition, waits while the lane is full.
+    #[instrument(skip_all)]
+    async fn handle_event(&mut self, message: BusMessage) -> Result<()> {
+        let key = (message.topic().to_string(), message.partition());
+        // a lane which panicked is replaced, its message is consumed again after a restart at the latest
+        if self.lanes.get(&key).is_none_or(|lane| lane.tx.is_closed()) {
+            let lane = self.spawn_lane();
+            self.lanes.insert(key.clone(), lane);
+        }
+        self.lanes[&key].tx.send(message).await
+            .map_err(|_| anyhow!("lane of {}/{} is closed", key.0, key.1))?;
+        Ok(())
+    }
+
+    /// Unhealthy while a partition is held back by a message its handlers keep failing on,
+    /// or which can't be dead-lettered.
+    fn health(&self) -> Result<()> {
+        for ((topic, partition), lane) in &self.lanes {
+            if let Some(failing) = lane.state.failing().as_ref() {
+                if failing.attempts >= STUCK_AFTER_ATTEMPTS {
+                    bail!(
+                        "{}/{} is stuck at offset {} after {} attempts: {}",
+                        topic, partition, failing.offset, failing.attempts, failing.error
+                    );
                 }
             }
-        }).await;
+        }
+        Ok(())
+    }
+
+    /// Lets the lanes finish what they have queued, then commits synchronously.
+    async fn drain(&mut self) {
+        // closing the senders ends the lanes once they are empty
+        self.lanes.clear();
+        while let Some(result) = self.lane_tasks.join_next().await {
+            if let Err(e) = result {
+                error!("Kafka consumer lane failed: {:?}", e);
+            }
+        }
+        if let Err(e) = self.handling.consumer.commit_sync() {
+            // nothing was consumed yet
+            info!("Nothing to commit on drain: {:?}", e);
+        }
+    }
+}
+
+impl KafkaConsumerTask {
+    fn spawn_lane(&mut self) -> Lane {
+        let (tx, rx) = mpsc::channel(LANE_CAPACITY);
+        let state = Arc::new(LaneState::default());
+        self.lane_tasks.spawn(run_lane(self.handling.clone(), state.clone(), rx));
+        Lane { tx, state }
+    }
+}
 
-        match jh {
-            Ok(_) => {
-                info!("task finished ok")
+async fn run_lane(
+    handling: Handling,
+    state: Arc<LaneState>,
+    mut rx: Receiver<BusMessage>,
+) {
+    while let Some(message) = rx.recv().await {
+        match parse(&message) {
+            Ok(event) => {
+                let span = info_span!(
+                    "order_event", order_id = event.order_id(), event_id = %event.event_id(), event_type = ?event.event_type()
+                );
+                telemetry::set_parent(&span, message.header(TRACEPARENT));
+                async {
+                    let mut attempt = 0;
+                    while let Err(e) = handling.process(&message, &event).await {
+                        warn!("Order event at {}/{}@{} failed: {:#}", message.topic(), message.partition(), message.offset(), e);
+                        attempt += 1;
+                        if attempt >= handling.dead_letter.max_attempts {
+                            handling.dead_letter(&message, &format!("{:#}", e), &state).await;
+                            break;
+                        }
+                        *state.failing() = Some(Failing { offset: message.offset(), attempts: attempt, error: format!("{:#}", e) });
+                        tokio::time::sleep(handling.backoff.delay(attempt - 1)).await;
+                    }
+                    *state.failing() = None;
+                }.instrument(span).await;
             }
             Err(e) => {
-                info!("task finished with error: {:?}", e)
+                error!("Malformed order event at {}/{}@{}: {:#}", message.topic(), message.partition(), message.offset(), e);
+                handling.dead_letter(&message, &format!("{:#}", e), &state).await;
             }
         }
+        // the next commit covers a lost one
+        if let Err(e) = handling.consumer.commit(&message) {
+            error!("Failed to commit {}/{}@{}: {:?}", message.topic(), message.partition(), message.offset(), e);
+        }
     }
+}
 
-    #[instrument(skip_all)]
-    pub async fn handle_kafka_consumer(
-        self,
-    ) -> Result<()> {
-        info!("Handling kafka consumer task");
-        self.consumer.subscribe(
-            &["order-topic"]
-        ).expect("Can't Subscribe");
-
-        loop {
-            match self.consumer.recv().await {
-                Err(e) => error!("{:?}",e),
-                Ok(message) => {
-                    match message.payload_view::<str>() {
-                        None => info!("None message"),
-                        Some(Ok(msg)) => info!("Message Consumed : {}", msg),
-                        Some(Err(e)) => error!("Error Parsing : {}",e)
-                    }
-                    self.consumer.commit_message(&message, CommitMode::Async).unwrap();
-                }
+impl Handling {
+    /// Runs the handlers in one transaction, which also moves the stored offset if there is one.
+    async fn process(&self, message: &BusMessage, event: &OrderEvent) -> Result<()> {
+        let mut tx = self.app_context.pg_pool().begin().await?;
+        if self.offsets == ConsumerOffsets::Postgres {
+            let advanced = ConsumerOffsetBmc::advance_tx(
+                &mut tx, GROUP_ID, message.topic(), message.partition(), message.offset(),
+            ).await?;
+            if !advanced {
+                // by this consumer before a restart or by another member of the group
+                info!("Order event at {}/{}@{} was already processed", message.topic(), message.partition(), message.offset());
+                return Ok(());
             }
         }
+        self.dispatcher.dispatch(&mut tx, event).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Publishes `message` to the dead-letter topic, retrying until it is stored,
+    /// its partition waits meanwhile, so nothing is committed before it is safe.
+    async fn dead_letter(&self, message: &BusMessage, error: &str, state: &LaneState) {
+        let record = dead_letter_record(&self.dead_letter.topic, message, error);
+        let mut attempt = 0;
+        while let Err(e) = self.app_context.message_bus().publish(&record).await {
+            warn!("Failed to dead-letter {}/{}@{}: {:#}", message.topic(), message.partition(), message.offset(), e);
+            attempt += 1;
+            *state.failing() = Some(Failing {
+                offset: message.offset(),
+                attempts: attempt,
+                error: format!("dead-lettering failed: {:#}", e),
+            });
+            tokio::time::sleep(self.backoff.delay(attempt - 1)).await;
+        }
+        *state.failing() = None;
+        warn!("Dead-lettered {}/{}@{} to {}: {}", message.topic(), message.partition(), message.offset(), self.dead_letter.topic, error);
     }
 }
 
-pub async fn create(app_config: AppConfig, group_id: &str) -> StreamConsumer {
-    info!("Creating Kafka Consumer");
+/// The offsets of `group_id` stored with the handlers' writes under `ConsumerOffsets::Postgres`.
+pub struct StoredOffsets {
+    app_context: Arc<ModelManager>,
+    group_id: &'static str,
+}
 
-    let mut config = ClientConfig::new();
-    config.set("bootstrap.servers", app_config.kafka_url.as_str())
-        .set("auto.offset.reset", "earliest")
-        .set("group.id", group_id)
-        .set("socket.timeout.ms","1000");
+impl StoredOffsets {
+    pub fn new(app_context: Arc<ModelManager>, group_id: &'static str) -> Self {
+        Self { app_context, group_id }
+    }
+}
 
-    let consumer : StreamConsumer =
-        config.create()
-            .expect("Fail to create consumer");
+impl StartOffsets for StoredOffsets {
+    fn start_offset<'a>(&'a self, topic: &'a str, partition: i32) -> BoxFuture<'a, Result<Option<i64>>> {
+        Box::pin(async move {
+            Ok(ConsumerOffsetBmc::next_offset(&self.app_context, self.group_id, topic, partition).await?)
+        })
+    }
+}
 
-    consumer
+fn parse(message: &BusMessage) -> Result<OrderEvent> {
+    let event: OrderEvent = serde_json::from_str(message.payload_str()?)?;
+    if event.schema_version() > ORDER_EVENT_SCHEMA_VERSION {
+        bail!("unsupported schema version {} of event {}", event.schema_version(), event.event_id());
+    }
+    Ok(event)
 }
 
 
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use crate::task::kafka::ORDER_TOPIC;
+
+    use super::*;
+
+    fn message(schema_version: u32) -> BusMessage {
+        let payload = format!(r#"{{"event_id": "1b4e28ba-2fa1-11d2-883f-0016d3cca427", "event_type": "order_delivered",
+            "schema_version": {schema_version}, "occurred_at": "2024-05-01T10:00:01Z",
+            "payload": {{"order_id": 7, "user_id": 1, "content": {{"content": []}}, "status": "delivered",
+                "created_at": "2024-05-01T10:00:00Z", "updated_at": "2024-05-01T10:00:01Z"}}}}"#);
+        BusMessage::new(ORDER_TOPIC, 0, 42, Some("7".to_string()), Some(payload.into_bytes()), vec![])
+    }
+
+    #[test]
+    fn test_parse_event() {
+        let event = parse(&message(ORDER_EVENT_SCHEMA_VERSION)).expect("must be ok");
+        assert_eq!(7, event.order_id());
+    }
+
+    #[test]
+    fn test_parse_newer_schema_fails() {
+        let error = parse(&message(ORDER_EVENT_SCHEMA_VERSION + 1)).expect_err("must fail");
+        assert!(error.to_string().starts_with("unsupported schema version"));
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use tracing::{info, instrument};
+
+use crate::bus::{BusMessage, BusRecord, MessageBus};
+
+/// Prefix of the headers a dead-lettered message gets besides its own.
+pub const DLQ_HEADER_PREFIX: &str = "dlq-";
+/// Why the message was given up on.
+pub const DLQ_ERROR: &str = "dlq-error";
+pub const DLQ_SOURCE_TOPIC: &str = "dlq-source-topic";
+pub const DLQ_SOURCE_PARTITION: &str = "dlq-source-partition";
+pub const DLQ_SOURCE_OFFSET: &str = "dlq-source-offset";
+
+const REPLAY_GROUP_ID: &str = "dead-letter-replay";
+/// A replay ends once the dead-letter topic stayed quiet this long.
+const REPLAY_IDLE: Duration = Duration::from_secs(5);
+
+/// Copy of `message` for the dead-letter `topic`, same key, payload and headers plus the `dlq-` ones.
+pub(crate) fn dead_letter_record(topic: &str, message: &BusMessage, error: &str) -> BusRecord {
+    republished(topic, message)
+        .with_header(DLQ_ERROR, error)
+        .with_header(DLQ_SOURCE_TOPIC, message.topic())
+        .with_header(DLQ_SOURCE_PARTITION, message.partition().to_string())
+        .with_header(DLQ_SOURCE_OFFSET, message.offset().to_string())
+}
+
+/// Moves up to `limit` messages of `dead_letter_topic` back to `topic`, without their `dlq-` headers,
+/// and returns how many were moved. Stops early once nothing arrived for a while.
+///
+/// A message is committed after it was republished, so an interrupted replay continues where it stopped
+/// and may publish its last message twice, which the consumers tolerate.
+#[instrument(skip(bus))]
+pub async fn replay(bus: &dyn MessageBus, dead_letter_topic: &str, topic: &str, limit: usize) -> Result<usize> {
+    let consumer = bus.subscribe(&[dead_letter_topic], REPLAY_GROUP_ID).await?;
+    let mut replayed = 0;
+    let result = async {
+        while replayed < limit {
+            let Ok(message) = tokio::time::timeout(REPLAY_IDLE, consumer.recv()).await else {
+                break;
+            };
+            let message = message?;
+            bus.publish(&republished(topic, &message)).await?;
+            consumer.commit(&message)?;
+            replayed += 1;
+        }
+        consumer.commit_sync()
+    }.await;
+    consumer.unsubscribe();
+    info!("Replayed {} dead-lettered messages to {}", replayed, topic);
+    result.map(|_| replayed)
+}
+
+/// `message` as it is published to `topic`, dropping what an earlier dead-lettering added.
+fn republished(topic: &str, message: &BusMessage) -> BusRecord {
+    let record = BusRecord::new(topic, message.key().unwrap_or_default(), message.payload().unwrap_or_default());
+    message.headers().iter()
+        .filter(|(key, _)| !key.starts_with(DLQ_HEADER_PREFIX))
+        .fold(record, |record, (key, value)| record.with_header(key, value))
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use crate::bus::memory::InMemoryBus;
+
+    use super::*;
+
+    const TOPIC: &str = "orders";
+    const DLQ: &str = "orders-dlq";
+
+    #[tokio::test]
+    async fn test_dead_letter_and_replay() {
+        let bus = InMemoryBus::new(1);
+        let record = BusRecord::new(TOPIC, "7", "not an event").with_header("traceparent", "t");
+        bus.publish(&record).await.expect("must be ok");
+        let consumer = bus.subscribe(&[TOPIC], "group").await.expect("must be ok");
+        let message = consumer.recv().await.expect("must be ok");
+
+        bus.publish(&dead_letter_record(DLQ, &message, "malformed")).await.expect("must be ok");
+        let dead_letters = bus.subscribe(&[DLQ], "inspect").await.expect("must be ok");
+        let dead = dead_letters.recv().await.expect("must be ok");
+        assert_eq!(Some("7"), dead.key());
+        assert_eq!("not an event", dead.payload_str().expect("must be ok"));
+        assert_eq!(Some("t"), dead.header("traceparent"));
+        assert_eq!(Some("malformed"), dead.header(DLQ_ERROR));
+        assert_eq!(Some(TOPIC), dead.header(DLQ_SOURCE_TOPIC));
+        assert_eq!(Some("0"), dead.header(DLQ_SOURCE_PARTITION));
+        assert_eq!(Some("0"), dead.header(DLQ_SOURCE_OFFSET));
+
+        assert_eq!(1, replay(&bus, DLQ, TOPIC, 1).await.expect("must be ok"));
+        let replayed = consumer.recv().await.expect("must be ok");
+        assert_eq!(Some("7"), replayed.key());
+        assert_eq!("not an event", replayed.payload_str().expect("must be ok"));
+        assert_eq!(vec![("traceparent".to_string(), "t".to_string())], replayed.headers());
+    }
+
+    #[tokio::test]
+    async fn test_replay_continues_after_limit() {
+        let bus = InMemoryBus::new(1);
+        for key in ["1", "2", "3"] {
+            bus.publish(&BusRecord::new(DLQ, key, "event")).await.expect("must be ok");
+        }
+
+        assert_eq!(2, replay(&bus, DLQ, TOPIC, 2).await.expect("must be ok"));
+        assert_eq!(1, replay(&bus, DLQ, TOPIC, 1).await.expect("must be ok"));
+
+        let consumer = bus.subscribe(&[TOPIC], "group").await.expect("must be ok");
+        let mut keys = Vec::new();
+        for _ in 0..3 {
+            keys.push(consumer.recv().await.expect("must be ok").key().map(str::to_string));
+        }
+        assert_eq!(vec![Some("1".to_string()), Some("2".to_string()), Some("3".to_string())], keys);
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use sqlx::{Postgres, Transaction};
+use tracing::debug;
+
+use lib_dto::event::{OrderEvent, OrderEventType};
+
+use crate::bmc::book_sales::BookSalesBmc;
+use crate::task::actor::BoxFuture;
+
+/// Reacts to the order events consumed from `KafkaProducerConfig::topic`.
+///
+/// Events are delivered at least once. Writes made in `tx` are committed together with the offset
+/// under `ConsumerOffsets::Postgres`, so they happen once, anything else must tolerate seeing an event again.
+pub trait OrderEventHandler: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn handle<'a>(&'a self, tx: &'a mut Transaction<'static, Postgres>, event: &'a OrderEvent) -> BoxFuture<'a, Result<()>>;
+}
+
+/// Hands every event to each registered handler, in registration order.
+#[derive(Clone, Default)]
+pub struct Dispatcher {
+    handlers: Vec<Arc<dyn OrderEventHandler>>,
+}
+
+impl Dispatcher {
+    pub fn register(mut self, handler: impl OrderEventHandler + 'static) -> Self {
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    /// The handlers `KafkaConsumerTask` dispatches to.
+    pub(crate) fn with_defaults() -> Self {
+        Self::default().register(BookSalesProjector)
+    }
+
+    /// Stops at the first failing handler, the whole event is retried in a new transaction,
+    /// so the handlers before it see it again.
+    pub async fn dispatch(&self, tx: &mut Transaction<'static, Postgres>, event: &OrderEvent) -> Result<()> {
+        for handler in &self.handlers {
+            handler.handle(tx, event).await
+                .with_context(|| format!("{} failed on order {}", handler.name(), event.order_id()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Projects the delivered orders into `book_sales`.
+struct BookSalesProjector;
+
+impl OrderEventHandler for BookSalesProjector {
+    fn name(&self) -> &'static str {
+        "BookSalesProjector"
+    }
+
+    fn handle<'a>(&'a self, tx: &'a mut Transaction<'static, Postgres>, event: &'a OrderEvent) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if event.event_type() != &OrderEventType::OrderDelivered {
+                return Ok(());
+            }
+            if !BookSalesBmc::add_delivered_tx(tx, event.payload()).await? {
+                debug!("Order {} was already projected", event.order_id());
+            }
+            Ok(())
+        })
+    }
+}
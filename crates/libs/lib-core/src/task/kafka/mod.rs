@@ -1,2 +1,108 @@
-pub(crate) mod producer_task;
-pub mod consumer_task;
\ No newline at end of file
+use std::time::Duration;
+
+use lib_dto::event::OrderEvent;
+use lib_dto::order::OrderStored;
+use lib_dto::outbox::OutboxForCreate;
+
+use crate::context::app_context::AppConfig;
+use crate::telemetry::current_traceparent;
+
+/// Default topic the order events are published to.
+pub const ORDER_TOPIC: &str = "order-topic";
+/// Default topic the order events nobody could handle are moved to.
+pub const DEAD_LETTER_TOPIC: &str = "order-topic-dlq";
+
+pub mod producer_task;
+pub mod consumer_task;
+pub mod dispatcher;
+pub mod dead_letter;
+pub mod republish;
+
+/// Where `KafkaConsumerTask` keeps how far it got.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConsumerOffsets {
+    /// Committed to the bus after the handlers succeeded, an event may be handled again after a crash.
+    #[default]
+    Kafka,
+    /// Stored in `consumer_offset` in the transaction of the handlers, so their writes happen once.
+    /// The offsets are still committed to the bus, so a restart doesn't fetch everything again.
+    Postgres,
+}
+
+/// Settings of the producer relaying the outbox, the consumers read `topic` as well.
+#[derive(Clone, Debug)]
+pub struct KafkaProducerConfig {
+    /// Topic the order events are published to.
+    pub topic: String,
+    /// `acks` of librdkafka, `all` is required by idempotence.
+    pub acks: String,
+    /// Lets the broker drop the duplicates of a retried send.
+    pub enable_idempotence: bool,
+    /// How long messages are batched before they are sent.
+    pub linger: Duration,
+    /// `compression.type` of librdkafka: none, gzip, snappy, lz4 or zstd.
+    pub compression: String,
+    /// How many outbox rows are claimed and awaited at once.
+    pub max_in_flight: usize,
+}
+
+impl Default for KafkaProducerConfig {
+    fn default() -> Self {
+        Self {
+            topic: ORDER_TOPIC.to_string(),
+            acks: "all".to_string(),
+            enable_idempotence: true,
+            linger: Duration::from_millis(5),
+            compression: "none".to_string(),
+            max_in_flight: 100,
+        }
+    }
+}
+
+/// How the topics missing at startup are created on the Kafka bus, existing ones are left as they are.
+#[derive(Clone, Debug)]
+pub struct KafkaTopicConfig {
+    pub partitions: i32,
+    /// Brokers keeping a copy of every partition, at most as many as the cluster has.
+    pub replication_factor: i32,
+    /// How long messages are kept, the broker default if none.
+    pub retention: Option<Duration>,
+}
+
+impl Default for KafkaTopicConfig {
+    fn default() -> Self {
+        Self {
+            partitions: 3,
+            replication_factor: 1,
+            retention: None,
+        }
+    }
+}
+
+/// Where `KafkaConsumerTask` moves the messages it gives up on.
+#[derive(Clone, Debug)]
+pub struct DeadLetterConfig {
+    /// Topic the malformed and failing messages are published to.
+    pub topic: String,
+    /// How many times the handlers of a message are tried before it is dead-lettered.
+    pub max_attempts: u32,
+}
+
+impl Default for DeadLetterConfig {
+    fn default() -> Self {
+        Self {
+            topic: DEAD_LETTER_TOPIC.to_string(),
+            max_attempts: 5,
+        }
+    }
+}
+
+/// The event published for an order, it goes through the outbox
+/// so it is only sent if the change of the order commits.
+/// Keyed by the order id, so the events of an order stay in one partition and in order.
+pub(crate) fn order_event(app_config: &AppConfig, order: &OrderStored) -> serde_json::Result<OutboxForCreate> {
+    let payload = serde_json::to_value(OrderEvent::new(order.clone()))?;
+    let traceparent = current_traceparent().or_else(|| order.traceparent().map(str::to_string));
+    let topic = app_config.kafka_producer.topic.as_str();
+    Ok(OutboxForCreate::new(topic, order.order_id().to_string(), payload).with_traceparent(traceparent))
+}
@@ -3,115 +3,195 @@ use std::time::Duration;
 
 use anyhow::Result;
 use log::error;
-use rdkafka::ClientConfig;
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::util::Timeout;
-use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::oneshot;
-use tracing::{info, instrument};
+use tokio::task::JoinSet;
+use tokio::time::{Interval, MissedTickBehavior};
+use tracing::{info, instrument, warn, Span};
 
-use lib_dto::order::OrderStored;
+use lib_dto::outbox::OutboxMessage;
 
-use crate::context::app_context::{AppConfig, ModelManager};
-use crate::task::kafka::producer_task::KafkaProducerResponse::HealthOk;
+use crate::bmc::outbox::OutboxBmc;
+use crate::bus::{BusRecord, MessageBus};
+use crate::context::app_context::ModelManager;
+use crate::task::actor::Actor;
+use crate::task::supervisor::{Backoff, RestartPolicy, TaskSpec};
+use crate::telemetry::{self, TRACEPARENT};
+
+/// How often the outbox is polled when nobody asks for a relay.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Rows claimed by a relay are left alone by other relays for this long.
+const CLAIM_LEASE: Duration = Duration::from_secs(30);
 
 #[derive(Debug)]
 pub enum KafkaProducerRequest {
-    Health(oneshot::Sender<KafkaProducerResponse>),
-    ProduceOrder(OrderStored),
+    /// New rows were committed to the outbox, relay them without waiting for the next poll.
+    /// The reply, if asked for, is sent once every due row was acknowledged or failed.
+    Relay(Option<oneshot::Sender<KafkaProducerResponse>>),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum KafkaProducerResponse {
-    HealthOk,
-    Produced,
-    FailedToProduce(OrderStored)
+    /// Every due row was acknowledged by the broker, this many in total.
+    Produced(usize),
+    /// Failed rows are retried with backoff, the outbox may also have been unreadable.
+    FailedToProduce { produced: usize, failed: usize },
 }
 
+/// Relays the outbox to the `MessageBus`.
+///
+/// A row is marked sent only after the broker acknowledged it, failed rows are retried with backoff.
+/// The outbox outlives the process, so delivery is at least once.
+/// Up to `KafkaProducerConfig::max_in_flight` rows are claimed and sent at once.
 pub(crate) struct KafkaProducerTask {
-    producer: FutureProducer,
+    app_context: Arc<ModelManager>,
+    bus: Arc<dyn MessageBus>,
+    max_in_flight: i64,
+    interval: Interval,
+    retry_backoff: Backoff,
 }
 
-impl KafkaProducerTask {
-    pub(crate) async fn start(
-        app_context: Arc<ModelManager>,
-    ) -> Sender<KafkaProducerRequest> {
-        info!("Starting kafka producer task");
-        let app_config = app_context.app_config();
-        let producer = create(app_config).await;
-        info!("created producer");
-        let task = {
-            Self { producer }
-        };
-        let (tx, rx) = tokio::sync::mpsc::channel(64);
-        tokio::spawn(task.handle_kafka_producer_requests(rx));
-        tx.clone()
+impl Actor for KafkaProducerTask {
+    type Request = KafkaProducerRequest;
+    type Event = ();
+
+    const NAME: &'static str = "KafkaProducerTask";
+
+    // the broker may be unreachable for a while, so the producer gives up instead of spinning forever
+    fn task_spec() -> TaskSpec {
+        TaskSpec::new(
+            Self::NAME,
+            RestartPolicy::Limited { max_restarts: 10, window: Duration::from_secs(60) },
+            Backoff::new(Duration::from_millis(500), Duration::from_secs(30)),
+        )
+    }
+
+    async fn create(app_context: Arc<ModelManager>) -> Result<Self> {
+        let bus = app_context.message_bus().clone();
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let max_in_flight = app_context.app_config().kafka_producer.max_in_flight.max(1) as i64;
+        Ok(Self {
+            app_context,
+            bus,
+            max_in_flight,
+            interval,
+            retry_backoff: Backoff::new(Duration::from_millis(500), Duration::from_secs(60)),
+        })
     }
 
     #[instrument(skip_all)]
-    pub async fn handle_kafka_producer_requests(
-        self,
-        mut kafka_rx: Receiver<KafkaProducerRequest>,
-    ) -> Result<()> {
-        info!("Handling kafka requests");
-
-        while let Some(request) = kafka_rx.recv().await {
-            info!("Got Kafka request: {:#?}", &request);
-
-            match request {
-                KafkaProducerRequest::Health(tx) => {
-                    tx.send(HealthOk).unwrap()
-                }
-                KafkaProducerRequest::ProduceOrder(order) => {
-                    let producer = self.producer.clone();
-                    tokio::spawn(produce(producer, order)).await.unwrap()
+    async fn handle(&mut self, request: KafkaProducerRequest) -> Result<()> {
+        match request {
+            KafkaProducerRequest::Relay(reply_tx) => {
+                let response = self.relay().await;
+                if let Some(reply_tx) = reply_tx {
+                    if reply_tx.send(response).is_err() {
+                        error!("failed to send kafka producer response")
+                    }
                 }
             }
         }
 
         Ok(())
     }
-}
 
-#[instrument(skip_all)]
-pub async fn create(app_config: &AppConfig) -> FutureProducer {
-    info!("Creating kafka producer");
+    async fn next_event(&mut self) -> Result<()> {
+        self.interval.tick().await;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _tick: ()) -> Result<()> {
+        // failed rows were logged as they were scheduled for a retry
+        self.relay().await;
+        Ok(())
+    }
 
-    info!("app_config: {:#?}", &app_config);
+    /// Relays what is already in the outbox and flushes everything buffered by the bus.
+    async fn drain(&mut self) {
+        self.relay().await;
+
+        if let Err(e) = self.bus.flush().await {
+            error!("Failed to flush message bus: {:?}", e);
+        }
+    }
+}
 
-    let mut config = ClientConfig::new();
-    config.set("bootstrap.servers", app_config.kafka_url.as_str());
-    //config.set("auto.create.topics.enable", "true");
+impl KafkaProducerTask {
+    /// Publishes due outbox rows batch by batch, until a batch comes back short.
+    async fn relay(&self) -> KafkaProducerResponse {
+        let (mut produced, mut failed) = (0, 0);
+        loop {
+            let messages = match OutboxBmc::claim_pending(&self.app_context, self.max_in_flight, CLAIM_LEASE).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    error!("Failed to claim outbox messages: {:?}", e);
+                    return KafkaProducerResponse::FailedToProduce { produced, failed };
+                }
+            };
+            let claimed = messages.len() as i64;
 
-    info!("config: {:#?}", &config);
+            let mut sends = JoinSet::new();
+            for message in messages {
+                sends.spawn(produce(self.bus.clone(), message));
+            }
+            while let Some(result) = sends.join_next().await {
+                match result {
+                    Ok((message, Ok(()))) => {
+                        produced += 1;
+                        if let Err(e) = OutboxBmc::mark_sent(&self.app_context, message.id()).await {
+                            // the row stays claimed and is sent again once the lease expires
+                            error!("Failed to mark outbox message {} sent: {:?}", message.id(), e)
+                        }
+                    }
+                    Ok((message, Err(e))) => {
+                        failed += 1;
+                        self.schedule_retry(&message, &e).await
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        error!("Outbox send panicked: {:?}", e)
+                    }
+                }
+            }
 
-    let producer : FutureProducer = config
-        .create()
-        .expect("Failure in creating producer");
+            if claimed < self.max_in_flight {
+                return match failed {
+                    0 => KafkaProducerResponse::Produced(produced),
+                    _ => KafkaProducerResponse::FailedToProduce { produced, failed },
+                };
+            }
+        }
+    }
 
-    producer
+    async fn schedule_retry(&self, message: &OutboxMessage, e: &anyhow::Error) {
+        let attempt = message.attempts().saturating_sub(1).max(0) as u32;
+        let delay = self.retry_backoff.delay(attempt);
+        warn!("Outbox message {} failed, retrying in {:?}: {:#}", message.id(), delay, e);
+        if let Err(e) = OutboxBmc::schedule_retry(&self.app_context, message.id(), delay, &format!("{:#}", e)).await {
+            error!("Failed to schedule retry of outbox message {}: {:?}", message.id(), e)
+        }
+    }
 }
 
+/// Sends one outbox row and waits until the bus stored it.
 #[instrument(skip_all)]
-pub async fn produce(
-    producer: FutureProducer,
-    order: OrderStored, ) {
-    info!("producing order: {:#?}", &order);
-    let res = serde_json::to_string(&order).unwrap();
-    let payload = res.as_str();
-    let record = FutureRecord::to("order-topic")
-        .payload(payload)
-        .key("Test-Key");
-
-    let status_delivery = producer
-        .send(record, Timeout::After(Duration::from_secs(2)))
-        .await;
-
-    match status_delivery {
-        Ok(report) => info!("Message Sent {:?}",report),
-        Err(e) => error!("Error producing.. {:?}",e)
+async fn produce(
+    bus: Arc<dyn MessageBus>,
+    message: OutboxMessage,
+) -> (OutboxMessage, Result<()>) {
+    telemetry::set_parent(&Span::current(), message.traceparent());
+    info!("producing outbox message: {:#?}", message.id());
+    let mut record = BusRecord::new(message.topic(), message.message_key(), message.payload().to_string());
+    if let Some(traceparent) = message.traceparent() {
+        record = record.with_header(TRACEPARENT, traceparent);
     }
-}
-
-
 
+    let result = match bus.publish(&record).await {
+        Ok((partition, offset)) => {
+            info!("Message Sent to {}/{}@{}", message.topic(), partition, offset);
+            Ok(())
+        }
+        Err(e) => Err(e),
+    };
+    (message, result)
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, instrument, warn};
+
+use lib_dto::event::{OrderRepublish, OrderRepublishReport};
+
+use crate::bmc::order::OrderBmc;
+use crate::bmc::outbox::OutboxBmc;
+use crate::context::app_context::ModelManager;
+use crate::task::kafka::order_event;
+use crate::task::main_task::TaskManager;
+
+/// Orders written to the outbox per transaction.
+const PAGE_SIZE: i64 = 100;
+
+/// Publishes the current event of every order matching `request` again, through the outbox
+/// like any other order event, so consumers which lost data can rebuild it.
+///
+/// Each page is relayed before the next one is read, progress is logged per page.
+/// A relay which fails leaves its rows to the next poll of the producer.
+#[instrument(skip(mm))]
+pub async fn republish_orders(mm: &ModelManager, request: &OrderRepublish) -> Result<OrderRepublishReport> {
+    let matched = OrderBmc::count_matching(mm, request).await?;
+    if request.dry_run() {
+        info!("Dry run, {} order events would be republished", matched);
+        return Ok(OrderRepublishReport::new(matched, 0, true));
+    }
+
+    let (page_size, mut pace) = match request.rate_limit() {
+        Some(rate_limit) => {
+            let rate_limit = rate_limit.max(1);
+            let page_size = PAGE_SIZE.min(rate_limit as i64);
+            let mut pace = tokio::time::interval(Duration::from_secs(page_size as u64) / rate_limit);
+            pace.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            (page_size, Some(pace))
+        }
+        None => (PAGE_SIZE, None),
+    };
+
+    let mut republished = 0;
+    let mut after_order_id = 0;
+    loop {
+        let orders = OrderBmc::get_matching(mm, request, after_order_id, page_size).await?;
+        let Some(last) = orders.last() else {
+            break;
+        };
+        after_order_id = last.order_id();
+        if let Some(pace) = pace.as_mut() {
+            pace.tick().await;
+        }
+
+        let mut tx = mm.pg_pool().begin().await?;
+        for order in &orders {
+            OutboxBmc::insert_tx(&mut tx, &order_event(mm.app_config(), order)?).await?;
+        }
+        tx.commit().await?;
+        republished += orders.len() as i64;
+
+        if let Err(e) = TaskManager::relay_outbox(mm.main_tx()).await {
+            warn!("Failed to relay republished order events: {:#}", e);
+        }
+        info!("Republished {}/{} order events", republished, matched);
+    }
+
+    Ok(OrderRepublishReport::new(matched, republished, false))
+}
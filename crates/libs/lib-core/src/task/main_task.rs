@@ -1,43 +1,65 @@
+use std::any::{Any, TypeId};
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use tokio::select;
 use tokio::sync::{oneshot};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::{error, info, instrument};
+use tokio::task::JoinSet;
+use tracing::{error, info, instrument, warn};
+
+use lib_dto::order::OrderStored;
 
 use crate::context::app_context::ModelManager;
+use crate::health::report::{check, CheckReport};
+use crate::notify::listener::ListenerStatus;
 use crate::notify::order::NotifyTask;
 use crate::select_cancel;
-use crate::task::delivery::{DeliveryRequest, DeliveryTask};
+use crate::task::actor::{self, Actor, ActorHandle, AnyActorHandle};
+use crate::task::delivery::DeliveryTask;
 use crate::task::kafka::consumer_task::KafkaConsumerTask;
-use crate::task::kafka::producer_task::{KafkaProducerRequest, KafkaProducerTask};
-use crate::task::order::{OrderRequest, OrderTask};
-use crate::task::storage::{StorageRequest, StorageTask};
+use crate::task::kafka::producer_task::{KafkaProducerRequest, KafkaProducerResponse, KafkaProducerTask};
+use crate::task::order::{submit, OrderTask};
+use crate::task::recovery::RecoveryTask;
+use crate::task::storage::StorageTask;
+use crate::task::status::TaskStatus;
+use crate::task::supervisor::{shared_channel, supervise, TaskRestarts, TaskStats};
+
+/// A relay claims up to `KafkaProducerConfig::max_in_flight` rows per batch, each send is bounded.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum MainTaskRequest {
     Health(oneshot::Sender<MainTaskResponse>),
     AppContext(oneshot::Sender<Arc<ModelManager>>),
-    OrderSender(oneshot::Sender<Sender<OrderRequest>>),
-    StorageSender(oneshot::Sender<Sender<StorageRequest>>),
-    DeliverySender(oneshot::Sender<Sender<DeliveryRequest>>),
-    KafkaProducerSender(oneshot::Sender<Sender<KafkaProducerRequest>>),
+    /// Looks up the handle of the actor type behind `TypeId`, the reply is an `ActorHandle<A::Request>`.
+    Actor(TypeId, oneshot::Sender<Option<Box<dyn Any + Send>>>),
+    RestartCounters(oneshot::Sender<Vec<TaskRestarts>>),
+    TaskStatuses(oneshot::Sender<Vec<TaskStatus>>),
+    ListenerStatus(oneshot::Sender<ListenerStatus>),
+    Shutdown(oneshot::Sender<MainTaskResponse>),
 }
 
 #[derive(Debug)]
 pub enum MainTaskResponse {
-    HealthOk,
+    Health(Vec<CheckReport>),
+    Drained,
+}
+
+#[derive(Clone)]
+struct ActorEntry {
+    type_id: TypeId,
+    handle: Arc<dyn AnyActorHandle>,
+    stats: Arc<TaskStats>,
 }
 
 #[derive(Clone)]
 pub struct TaskManager {
     app_context: Arc<ModelManager>,
     //tx: Sender<MainTaskRequest>,
-    order_tx: Option<Sender<OrderRequest>>,
-    storage_tx: Option<Sender<StorageRequest>>,
-    delivery_tx: Option<Sender<DeliveryRequest>>,
-    kafka_producer_tx: Option<Sender<KafkaProducerRequest>>,
+    // in start order, which is also the order the pipeline is drained in
+    actors: Vec<ActorEntry>,
 }
 
 impl TaskManager {
@@ -46,30 +68,19 @@ impl TaskManager {
         rx: Receiver<MainTaskRequest>,
         app_context: Arc<ModelManager>
     ) -> Result<()> {
-        info!("Starting NotifyTask");
-        let cancellation_token = app_context.cancellation_token();
-        let app_context_cloned = Arc::clone(&app_context);
-        select_cancel!(NotifyTask::start(app_context_cloned), cancellation_token);
-
-        info!("Starting KafkaConsumerTask");
-        let cancellation_token = app_context.clone().cancellation_token();
-        let app_context_cloned = Arc::clone(&app_context);
-        select_cancel!(KafkaConsumerTask::start(app_context_cloned), cancellation_token);
-
-        let order_tx = None;
-        let storage_tx = None;
-        let delivery_tx = None;
-        let kafka_producer_tx = None;
-
-        let app_context_cloned = Arc::clone(&app_context);
+        let mut actors = Vec::new();
+        // upstream stages first, each stage only gets work from the previous one
+        Self::spawn_actor::<NotifyTask>(&app_context, &mut actors);
+        Self::spawn_actor::<RecoveryTask>(&app_context, &mut actors);
+        Self::spawn_actor::<KafkaConsumerTask>(&app_context, &mut actors);
+        Self::spawn_actor::<OrderTask>(&app_context, &mut actors);
+        Self::spawn_actor::<StorageTask>(&app_context, &mut actors);
+        Self::spawn_actor::<DeliveryTask>(&app_context, &mut actors);
+        Self::spawn_actor::<KafkaProducerTask>(&app_context, &mut actors);
 
         let main_task = TaskManager {
-            app_context: app_context_cloned,
-            //tx: tx.clone(),
-            order_tx,
-            storage_tx,
-            delivery_tx,
-            kafka_producer_tx,
+            app_context: Arc::clone(&app_context),
+            actors,
         };
 
         info!("Spawning MainTask");
@@ -81,6 +92,21 @@ impl TaskManager {
         Ok(())
     }
 
+    /// Creates the mailbox of `A` and supervises it, the handle stays valid across restarts.
+    fn spawn_actor<A: Actor>(app_context: &Arc<ModelManager>, actors: &mut Vec<ActorEntry>) {
+        let (tx, rx) = shared_channel(A::MAILBOX_CAPACITY);
+        let stats = Arc::new(TaskStats::default());
+        let app_context_cloned = Arc::clone(app_context);
+        supervise(A::task_spec(), stats.clone(), app_context.cancellation_token(), move || {
+            actor::start::<A>(Arc::clone(&app_context_cloned), rx.clone())
+        });
+        actors.push(ActorEntry {
+            type_id: TypeId::of::<A>(),
+            handle: Arc::new(ActorHandle::new(A::NAME, tx)),
+            stats,
+        });
+    }
+
     #[instrument(skip_all)]
     async fn handle_requests(
         mut self,
@@ -99,7 +125,17 @@ impl TaskManager {
     async fn match_requests(&mut self, request: MainTaskRequest) {
         info!("matching MainTaskRequest: {:?}", request);
         match request {
-            MainTaskRequest::Health(_) => {}
+            MainTaskRequest::Health(tx) => {
+                info!("matching Health");
+                // tasks may be waiting on this loop themselves, so the checks must not block it
+                let task_manager = self.clone();
+                tokio::spawn(async move {
+                    let checks = task_manager.check_tasks().await;
+                    if tx.send(MainTaskResponse::Health(checks)).is_err() {
+                        error!("failed to send health")
+                    }
+                });
+            }
             MainTaskRequest::AppContext(tx) => {
                 info!("matching AppContext");
                 let result = tx.send(self.app_context.clone());
@@ -112,87 +148,123 @@ impl TaskManager {
                     }
                 }
             }
-            MainTaskRequest::OrderSender(tx) => {
-                info!("matching OrderSender");
-                if let Err(e) = self.check_order_task().await {
-                    error!("Failed to check task: {:#?}", e);
-                    self.order_tx = Some(OrderTask::start(Arc::clone(&self.app_context)));
+            MainTaskRequest::Actor(type_id, tx) => {
+                info!("matching Actor");
+                let handle = self.actors.iter()
+                    .find(|entry| entry.type_id == type_id)
+                    .map(|entry| entry.handle.clone_any());
+                if tx.send(handle).is_err() {
+                    error!("failed to send actor handle")
                 }
-                tx.send(self.order_tx.as_ref().expect("must be some").clone()).expect("should be ok");
             }
-            MainTaskRequest::StorageSender(tx) => {
-                info!("matching StorageSender");
-                if let Err(e) = self.check_storage_task().await {
-                    error!("Failed to check task: {:#?}", e);
-                    self.storage_tx = Some(StorageTask::start(Arc::clone(&self.app_context)));
-                }
-                tx.send(self.storage_tx.as_ref().expect("must be some").clone()).expect("TODO: panic message");
+            MainTaskRequest::Shutdown(tx) => {
+                info!("matching Shutdown");
+                let task_manager = self.clone();
+                let deadline = self.app_context.app_config().shutdown_deadline;
+                tokio::spawn(async move {
+                    if tokio::time::timeout(deadline, task_manager.drain()).await.is_err() {
+                        warn!("Shutdown deadline of {:?} exceeded, remaining orders are cancelled", deadline);
+                    }
+                    if tx.send(MainTaskResponse::Drained).is_err() {
+                        error!("failed to send shutdown response")
+                    }
+                });
             }
-            MainTaskRequest::DeliverySender(tx) => {
-                info!("matching DeliverySender");
-                if let Err(e) = self.check_delivery_task().await {
-                    error!("Failed to check task: {:#?}", e);
-                    self.delivery_tx = Some(DeliveryTask::start(Arc::clone(&self.app_context)));
+            MainTaskRequest::RestartCounters(tx) => {
+                info!("matching RestartCounters");
+                let counters = self.actors.iter()
+                    .map(|entry| TaskRestarts::new(entry.handle.name(), entry.stats.restarts()))
+                    .collect();
+                if tx.send(counters).is_err() {
+                    error!("failed to send restart counters")
                 }
-                tx.send(self.delivery_tx.as_ref().expect("must be some").clone()).expect("TODO: panic message");
             }
-            MainTaskRequest::KafkaProducerSender(tx) => {
-                info!("matching KafkaProducerSender");
-                if let Err(e) = self.check_kafka_producer_task().await {
-                    error!("Failed to check task: {:#?}", e);
-                    self.kafka_producer_tx = Some(KafkaProducerTask::start(Arc::clone(&self.app_context)).await);
+            MainTaskRequest::ListenerStatus(tx) => {
+                info!("matching ListenerStatus");
+                if tx.send(self.app_context.listener_stats().status()).is_err() {
+                    error!("failed to send listener status")
                 }
-                tx.send(self.kafka_producer_tx.as_ref().expect("must be some").clone()).expect("TODO: panic message");
+            }
+            MainTaskRequest::TaskStatuses(tx) => {
+                info!("matching TaskStatuses");
+                // tasks answer through their mailboxes, which must not block this loop
+                let task_manager = self.clone();
+                tokio::spawn(async move {
+                    let statuses = task_manager.task_statuses().await;
+                    if tx.send(statuses).is_err() {
+                        error!("failed to send task statuses")
+                    }
+                });
             }
         }
     }
 
     #[instrument(skip_all)]
-    async fn check_order_task(&mut self, ) -> Result<()> {
-        let (health_tx, health_rx) = oneshot::channel();
-        if let Some(tx) = self.order_tx.as_ref() {
-            tx.send(OrderRequest::Health(health_tx)).await?;
-            health_rx.await?;
-            return Ok(());
+    async fn check_tasks(&self) -> Vec<CheckReport> {
+        let mut checks = JoinSet::new();
+        for (index, entry) in self.actors.iter().enumerate() {
+            let handle = entry.handle.clone();
+            checks.spawn(async move { (index, check(handle.name(), handle.health()).await) });
         }
-        bail!("order tx none")
+        let mut reports = checks.join_all().await;
+        reports.sort_by_key(|(index, _)| *index);
+        reports.into_iter().map(|(_, report)| report).collect()
     }
 
     #[instrument(skip_all)]
-    async fn check_storage_task(&mut self, ) -> Result<()> {
-        let (health_tx, health_rx) = oneshot::channel();
-        if let Some(tx) = self.storage_tx.as_ref() {
-            tx.send(StorageRequest::Health(health_tx)).await?;
-            health_rx.await?;
-            return Ok(());
+    async fn task_statuses(&self) -> Vec<TaskStatus> {
+        let mut statuses = JoinSet::new();
+        for (index, entry) in self.actors.iter().enumerate() {
+            let entry = entry.clone();
+            statuses.spawn(async move {
+                (index, TaskStatus::collect(entry.handle.as_ref(), &entry.stats).await)
+            });
         }
-        bail!("storage tx none")
+        let mut statuses = statuses.join_all().await;
+        statuses.sort_by_key(|(index, _)| *index);
+        statuses.into_iter().map(|(_, status)| status).collect()
     }
 
     #[instrument(skip_all)]
-    async fn check_delivery_task(&mut self, ) -> Result<()> {
-        let (health_tx, health_rx) = oneshot::channel();
-        if let Some(tx) = self.delivery_tx.as_ref() {
-            tx.send(DeliveryRequest::Health(health_tx)).await?;
-            health_rx.await?;
-            return Ok(());
+    pub async fn health(main_tx: Sender<MainTaskRequest>) -> Result<Vec<CheckReport>> {
+        let (tx, rx) = oneshot::channel();
+        main_tx.send(MainTaskRequest::Health(tx)).await?;
+        match rx.await? {
+            MainTaskResponse::Health(checks) => Ok(checks),
+            response => bail!("unexpected health response: {:?}", response),
         }
-        bail!("delivery tx none")
     }
 
+    /// Stops taking new orders, lets the pipeline finish the queued ones within
+    /// `AppConfig::shutdown_deadline` and only then fires the cancellation token.
+    #[instrument(skip_all)]
+    pub async fn shutdown(app_context: &ModelManager) -> Result<()> {
+        info!("Shutting down tasks");
+        let deadline = app_context.app_config().shutdown_deadline;
+        let drained = async {
+            let (tx, rx) = oneshot::channel();
+            app_context.main_tx().send(MainTaskRequest::Shutdown(tx)).await?;
+            // the main loop applies the deadline itself, this only guards against it being gone
+            tokio::time::timeout(deadline + Duration::from_secs(1), rx).await??;
+            Ok(())
+        }.await;
+
+        app_context.cancellation_token().cancel();
+        drained
+    }
+
+    /// Drains the actors in start order, so every stage is empty before the next one is drained.
     #[instrument(skip_all)]
-    async fn check_kafka_producer_task(&mut self, ) -> Result<()> {
-        let (health_tx, health_rx) = oneshot::channel();
-        if let Some(tx) = self.kafka_producer_tx.as_ref() {
-            tx.send(KafkaProducerRequest::Health(health_tx)).await?;
-            health_rx.await?;
-            return Ok(());
+    async fn drain(&self) {
+        for entry in &self.actors {
+            if let Err(e) = entry.handle.drain().await {
+                warn!("Failed to drain {}: {:#}", entry.handle.name(), e);
+            }
         }
-        bail!("kafka producer tx none")
     }
 
     #[instrument(skip_all)]
-    pub(crate) async fn app_context(main_tx: Sender<MainTaskRequest>) -> Result<Arc<ModelManager>> {
+    pub async fn app_context(main_tx: Sender<MainTaskRequest>) -> Result<Arc<ModelManager>> {
         info!("Called app_context");
         let (tx, rx) = oneshot::channel();
         main_tx.send(MainTaskRequest::AppContext(tx)).await?;
@@ -200,39 +272,49 @@ impl TaskManager {
     }
 
     #[instrument(skip_all)]
-    pub(crate) async fn order_sender(main_tx: Sender<MainTaskRequest>) -> Result<Sender<OrderRequest>> {
-        info!("Called order_sender");
+    pub async fn actor<A: Actor>(main_tx: Sender<MainTaskRequest>) -> Result<ActorHandle<A::Request>> {
         let (tx, rx) = oneshot::channel();
-        main_tx.send(MainTaskRequest::OrderSender(tx)).await?;
-        let sender = rx.await?;
-        info!("Returning order_sender");
-        Ok(sender)
+        main_tx.send(MainTaskRequest::Actor(TypeId::of::<A>(), tx)).await?;
+        let handle = rx.await?.ok_or_else(|| anyhow!("{} is not started", A::NAME))?;
+        handle.downcast::<ActorHandle<A::Request>>()
+            .map(|handle| *handle)
+            .map_err(|_| anyhow!("{} handle has an unexpected type", A::NAME))
     }
 
+    /// Feeds an order into the pipeline, for orders which don't come through the notifications.
     #[instrument(skip_all)]
-    pub(crate) async fn storage_sender(main_tx: Sender<MainTaskRequest>) -> Result<Sender<StorageRequest>> {
-        info!("Called storage_sender");
+    pub async fn submit_order(main_tx: Sender<MainTaskRequest>, order: OrderStored) -> Result<()> {
+        let order_task = Self::actor::<OrderTask>(main_tx).await?;
+        submit(&order_task, order).await
+    }
+
+    /// Publishes the due outbox rows now and reports how it went.
+    #[instrument(skip_all)]
+    pub async fn relay_outbox(main_tx: Sender<MainTaskRequest>) -> Result<KafkaProducerResponse> {
+        let producer = Self::actor::<KafkaProducerTask>(main_tx).await?;
+        producer.call(|tx| KafkaProducerRequest::Relay(Some(tx)), RELAY_TIMEOUT).await
+    }
+
+    /// Snapshot of every task, in start order.
+    #[instrument(skip_all)]
+    pub async fn statuses(main_tx: Sender<MainTaskRequest>) -> Result<Vec<TaskStatus>> {
         let (tx, rx) = oneshot::channel();
-        main_tx.send(MainTaskRequest::StorageSender(tx)).await?;
+        main_tx.send(MainTaskRequest::TaskStatuses(tx)).await?;
         Ok(rx.await?)
     }
 
+    /// Connection state and reconnect count of the order notification listener.
     #[instrument(skip_all)]
-    pub(crate) async fn delivery_sender(main_tx: Sender<MainTaskRequest>) -> Result<Sender<DeliveryRequest>> {
-        info!("Called delivery_sender");
+    pub async fn listener_status(main_tx: Sender<MainTaskRequest>) -> Result<ListenerStatus> {
         let (tx, rx) = oneshot::channel();
-        main_tx.send(MainTaskRequest::DeliverySender(tx)).await?;
-        info!("Returning delivery_sender");
+        main_tx.send(MainTaskRequest::ListenerStatus(tx)).await?;
         Ok(rx.await?)
     }
 
     #[instrument(skip_all)]
-    pub(crate) async fn kafka_producer_sender(
-        main_tx: Sender<MainTaskRequest>
-    ) -> Result<Sender<KafkaProducerRequest>> {
+    pub async fn restart_counters(main_tx: Sender<MainTaskRequest>) -> Result<Vec<TaskRestarts>> {
         let (tx, rx) = oneshot::channel();
-        main_tx.send(MainTaskRequest::KafkaProducerSender(tx)).await?;
+        main_tx.send(MainTaskRequest::RestartCounters(tx)).await?;
         Ok(rx.await?)
     }
 }
-
@@ -1,5 +1,13 @@
+pub mod actor;
+pub(crate) mod book_locks;
 pub mod main_task;
 pub(crate) mod order;
 pub(crate) mod storage;
 pub(crate) mod delivery;
-pub mod kafka;
\ No newline at end of file
+pub(crate) mod in_flight;
+pub mod kafka;
+pub(crate) mod recovery;
+pub mod retry;
+pub mod status;
+pub mod supervisor;
+pub mod worker_pool;
\ No newline at end of file
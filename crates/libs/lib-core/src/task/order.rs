@@ -1,76 +1,235 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::Result;
-use tokio::select;
-use tokio::sync::mpsc::{Receiver, Sender};
+use anyhow::{bail, Result};
 use tokio::sync::oneshot;
-use tracing::{info, instrument};
+use tracing::{error, info, instrument, Instrument};
 
-use lib_dto::order::OrderStored;
+use lib_dto::order::{OrderStatus, OrderStored};
 
+use crate::bmc::general::fail_order;
+use crate::bmc::order::OrderBmc;
 use crate::context::app_context::ModelManager;
-use crate::select_cancel;
-use crate::task::delivery::DeliveryRequest;
-use crate::task::main_task::{TaskManager};
-use crate::task::storage::StorageRequest;
+use crate::error::Error;
+use crate::order::state_machine::is_terminal;
+use crate::task::actor::{Actor, ActorHandle, ActorReport};
+use crate::task::book_locks::BookLocks;
+use crate::task::delivery::{DeliveryRequest, DeliveryResponse, DeliveryTask};
+use crate::task::in_flight::InFlight;
+use crate::task::main_task::TaskManager;
+use crate::task::storage::{StorageRequest, StorageResponse, StorageTask};
+use crate::task::worker_pool::{Worker, WorkerPool};
+use crate::telemetry::order_span;
+
+/// Bounds how long the answer for a single submitted order is awaited.
+const ORDER_TIMEOUT: Duration = Duration::from_secs(30);
+/// Storage and delivery answer once their bounded retries are over, this only guards against a lost stage.
+const STAGE_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub enum OrderRequest {
-    Health(oneshot::Sender<OrderResponse>),
     ProcessOrder(OrderStored, oneshot::Sender<OrderResponse>),
+    /// The order was deleted, its processing is aborted in every stage.
+    Abort(i64),
 }
 
 #[derive(Debug)]
 pub enum OrderResponse {
-    HealthOk,
     Processed,
-    FailedToProcess(OrderStored)
+    /// The customer cancelled the order before the pipeline got to it.
+    Cancelled,
+    /// Another submission of the same order is ahead, recovery may submit an order twice.
+    Skipped,
+    FailedToProcess(OrderStored),
+}
+
+/// Processes up to `AppConfig::order_workers` orders at once.
+/// Orders sharing a book are serialized by `BookLocks`, disjoint ones run in parallel.
+pub(crate) struct OrderTask {
+    stages: Stages,
+    workers: WorkerPool,
+    in_flight: InFlight,
+}
+
+#[derive(Clone)]
+struct Stages {
+    app_context: Arc<ModelManager>,
+    storage: ActorHandle<StorageRequest>,
+    delivery: ActorHandle<DeliveryRequest>,
+    book_locks: BookLocks,
 }
 
-pub(crate) struct OrderTask {}
+impl Actor for OrderTask {
+    type Request = OrderRequest;
+    type Event = ();
 
-impl OrderTask {
-    pub(crate) fn start(
-        app_context: Arc<ModelManager>,
-    ) -> Sender<OrderRequest> {
-        let (tx, rx) = tokio::sync::mpsc::channel(64);
+    const NAME: &'static str = "OrderTask";
 
-        let cancellation_token = app_context.cancellation_token();
-        select_cancel!(handle_order(app_context, rx), cancellation_token);
-        tx.clone()
+    async fn create(app_context: Arc<ModelManager>) -> Result<Self> {
+        let main_tx = app_context.main_tx();
+        let storage = TaskManager::actor::<StorageTask>(main_tx.clone()).await?;
+        let delivery = TaskManager::actor::<DeliveryTask>(main_tx).await?;
+        let workers = WorkerPool::new(app_context.app_config().order_workers);
+        Ok(Self {
+            stages: Stages { app_context, storage, delivery, book_locks: BookLocks::default() },
+            workers,
+            in_flight: InFlight::default(),
+        })
     }
-}
 
-#[instrument(skip_all)]
-pub async fn handle_order(
-    app_context: Arc<ModelManager>,
-    mut order_rx: Receiver<OrderRequest>,
-) -> Result<()> {
-    let main_tx = app_context.main_tx();
-    let storage_tx = TaskManager::storage_sender(main_tx.clone()).await?;
-    let delivery_tx = TaskManager::delivery_sender(main_tx.clone()).await?;
-
-    while let Some(order_request) = order_rx.recv().await {
-        info!("received order is {:#?}", &order_request);
-
-        match order_request {
-            OrderRequest::Health(tx) => {
-                tx.send(OrderResponse::HealthOk).expect("TODO: panic message");
+    #[instrument(skip_all)]
+    async fn handle(&mut self, request: OrderRequest) -> Result<()> {
+        info!("received order is {:#?}", &request);
+        self.in_flight.reap();
+
+        match request {
+            OrderRequest::ProcessOrder(order, tx) if self.in_flight.contains(order.order_id()) => {
+                info!("Order {} is already in flight, skipping it", order.order_id());
+                if tx.send(OrderResponse::Skipped).is_err() {
+                    error!("failed to send order response")
+                }
             }
             OrderRequest::ProcessOrder(order, tx) => {
-                let (storage_resp_tx, storage_resp_rx) = oneshot::channel();
-                //todo deal with clone()
-                storage_tx.send(StorageRequest::UpdateStorage(order.clone(), storage_resp_tx)).await.unwrap();
-                let _storage_resp = storage_resp_rx.await.unwrap();
+                // waiting here keeps the backpressure in the mailbox while every worker is busy
+                let worker = self.workers.acquire().await;
+                let stages = self.stages.clone();
+                let span = order_span(Self::NAME, &order);
+                self.in_flight.spawn(order.order_id(), async move {
+                    let response = stages.process(order, worker).await;
+                    if tx.send(response).is_err() {
+                        error!("failed to send order response")
+                    }
+                }.instrument(span));
+            }
+            OrderRequest::Abort(order_id) => {
+                if self.in_flight.abort(order_id) {
+                    info!("Aborted processing of order {}", order_id);
+                }
+                self.stages.storage.cast(StorageRequest::Abort(order_id)).await?;
+                self.stages.delivery.cast(DeliveryRequest::Abort(order_id)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn report(&mut self) -> ActorReport {
+        self.in_flight.reap();
+        ActorReport {
+            in_flight_orders: self.in_flight.order_ids(),
+            worker_pool: Some(self.workers.metrics()),
+        }
+    }
+
+    async fn drain(&mut self) {
+        self.in_flight.join_all().await;
+    }
+}
+
+impl Stages {
+    async fn process(&self, order: OrderStored, worker: Worker) -> OrderResponse {
+        let (_books, contended) = self.book_locks
+            .lock(order.content().iter().map(|item| item.book_id())).await;
+        if contended {
+            worker.contended();
+        }
 
-                let (delivery_resp_tx, delivery_resp_rx) = oneshot::channel();
-                delivery_tx.send(DeliveryRequest::Deliver(order, delivery_resp_tx)).await.unwrap();
-                let _delivery_resp = delivery_resp_rx.await.unwrap();
+        match self.update(order.clone()).await {
+            // a cancellation makes the next stage an illegal move, so it is skipped as well
+            Ok(OrderResponse::Skipped) => match self.status(order.order_id()).await {
+                Some(OrderStatus::Cancelled) => {
+                    info!("Order {} was cancelled, skipping it", order.order_id());
+                    OrderResponse::Cancelled
+                }
+                _ => {
+                    info!("Order {} was moved on by someone else, skipping it", order.order_id());
+                    OrderResponse::Skipped
+                }
+            },
+            Ok(response) => response,
+            Err(e) => match self.status(order.order_id()).await {
+                // a stage rejected the order and recorded why
+                Some(OrderStatus::Failed) => {
+                    info!("Order {} was rejected: {:#}", order.order_id(), e);
+                    OrderResponse::FailedToProcess(order)
+                }
+                _ => {
+                    error!("Failed to process order {}: {:#?}", order.order_id(), e);
+                    let reason = format!("{:#}", e);
+                    if let Err(e) = fail_order(&self.app_context, order.order_id(), OrderTask::NAME, &reason).await {
+                        error!("Failed to mark order {} failed: {:#?}", order.order_id(), e);
+                    }
+                    OrderResponse::FailedToProcess(order)
+                }
+            },
+        }
+    }
 
-                tx.send(OrderResponse::Processed).unwrap();
+    async fn status(&self, order_id: i64) -> Option<OrderStatus> {
+        match OrderBmc::get_by_id(&self.app_context, order_id).await {
+            Ok(order) => Some(order.status().clone()),
+            Err(e) => {
+                error!("Failed to check status of order {}: {:#?}", order_id, e);
+                None
             }
         }
     }
 
+    /// Runs the stages the order still needs, a recovered order resumes where it was left.
+    async fn update(&self, order: OrderStored) -> Result<OrderResponse> {
+        let resume_at = order.status().clone();
+        if resume_at == OrderStatus::New {
+            let started = OrderBmc::transition(
+                &self.app_context, order.order_id(), OrderStatus::InProgress, OrderTask::NAME, None,
+            ).await;
+            match started {
+                Ok(_) => {}
+                Err(Error::IllegalTransition { .. }) => return Ok(OrderResponse::Skipped),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        if matches!(resume_at, OrderStatus::New | OrderStatus::InProgress) {
+            //todo deal with clone()
+            let storage_resp = self.storage
+                .call(|tx| StorageRequest::UpdateStorage(order.clone(), tx), STAGE_TIMEOUT).await?;
+            match storage_resp {
+                StorageResponse::Updated => {}
+                StorageResponse::Skipped => return Ok(OrderResponse::Skipped),
+                StorageResponse::FailedToUpdate(order) => bail!("storage was not updated for order {}", order.order_id()),
+            }
+        }
+
+        if is_terminal(&resume_at) {
+            return Ok(OrderResponse::Skipped);
+        }
+        let delivery_resp = self.delivery
+            .call(|tx| DeliveryRequest::Deliver(order, tx), STAGE_TIMEOUT).await?;
+        match delivery_resp {
+            DeliveryResponse::Delivered => Ok(OrderResponse::Processed),
+            DeliveryResponse::Skipped => Ok(OrderResponse::Skipped),
+            DeliveryResponse::FailedToDeliver(order_id) => bail!("order {} was not delivered", order_id),
+        }
+    }
+}
+
+/// Hands the order to `OrderTask`. Orders are processed concurrently,
+/// so the answer is awaited off the caller's loop and only logged.
+pub(crate) async fn submit(order_task: &ActorHandle<OrderRequest>, order: OrderStored) -> Result<()> {
+    let (result_tx, result_rx) = oneshot::channel();
+    order_task.cast(OrderRequest::ProcessOrder(order, result_tx)).await?;
+    tokio::spawn(async move {
+        // the order task may be restarted by the supervisor while processing
+        match tokio::time::timeout(ORDER_TIMEOUT, result_rx).await {
+            Ok(Ok(OrderResponse::Processed)) => info!("Order processed"),
+            Ok(Ok(OrderResponse::Cancelled)) => info!("Order cancelled before processing"),
+            Ok(Ok(OrderResponse::Skipped)) => info!("Order skipped, it is processed elsewhere"),
+            Ok(Ok(OrderResponse::FailedToProcess(order))) => {
+                error!("Failed to process order {}", order.order_id())
+            }
+            Ok(Err(e)) => error!("Failed to receive order response: {:#?}", e),
+            Err(_) => error!("No order response within {:?}", ORDER_TIMEOUT),
+        }
+    });
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tracing::{error, info, instrument};
+
+use crate::bmc::order::OrderBmc;
+use crate::context::app_context::ModelManager;
+use crate::task::actor::{Actor, ActorHandle};
+use crate::task::main_task::TaskManager;
+use crate::task::order::{submit, OrderRequest, OrderTask};
+
+/// The recovery task is driven by its sweep interval, it takes no requests besides the built-in ones.
+#[derive(Debug)]
+pub enum RecoveryRequest {}
+
+/// Sends unfinished orders through the pipeline again.
+///
+/// On start every unfinished order is recovered, an order is left mid-pipeline when the process
+/// stops before finishing it. Afterwards the orders untouched for `AppConfig::stale_order_after`
+/// are swept periodically. `OrderTask` resumes an order at the stage its status points to.
+pub(crate) struct RecoveryTask {
+    app_context: Arc<ModelManager>,
+    order: ActorHandle<OrderRequest>,
+    // None once drained, so no orders are recovered during a shutdown
+    interval: Option<Interval>,
+}
+
+impl Actor for RecoveryTask {
+    type Request = RecoveryRequest;
+    type Event = ();
+
+    const NAME: &'static str = "RecoveryTask";
+
+    async fn create(app_context: Arc<ModelManager>) -> Result<Self> {
+        let order = TaskManager::actor::<OrderTask>(app_context.main_tx()).await?;
+        let period = app_context.app_config().stale_order_after;
+        // the first sweep is done by `started`
+        let mut interval = tokio::time::interval_at(Instant::now() + period, period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Ok(Self { app_context, order, interval: Some(interval) })
+    }
+
+    async fn handle(&mut self, request: RecoveryRequest) -> Result<()> {
+        match request {}
+    }
+
+    async fn next_event(&mut self) -> Result<()> {
+        let Some(interval) = self.interval.as_mut() else {
+            return std::future::pending().await;
+        };
+        interval.tick().await;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _tick: ()) -> Result<()> {
+        self.recover(self.app_context.app_config().stale_order_after).await
+    }
+
+    async fn started(&mut self) -> Result<()> {
+        self.recover(Duration::ZERO).await
+    }
+
+    async fn drain(&mut self) {
+        self.interval = None;
+    }
+}
+
+impl RecoveryTask {
+    #[instrument(skip(self))]
+    async fn recover(&self, untouched_for: Duration) -> Result<()> {
+        let orders = OrderBmc::get_unfinished(&self.app_context, untouched_for).await?;
+        if orders.is_empty() {
+            return Ok(());
+        }
+        info!("Recovering {} unfinished orders", orders.len());
+        for order in orders {
+            let order_id = order.order_id();
+            if let Err(e) = submit(&self.order, order).await {
+                error!("Failed to recover order {}: {:#?}", order_id, e);
+            }
+        }
+        Ok(())
+    }
+}
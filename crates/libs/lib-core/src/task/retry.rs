@@ -0,0 +1,148 @@
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use tracing::{info, warn};
+
+use crate::bmc::general::{dead_letter_order, fail_order};
+use crate::context::app_context::ModelManager;
+use crate::error::{Error, Result};
+use crate::task::supervisor::Backoff;
+
+/// Retries of the storage and delivery updates.
+///
+/// Only serialization failures and deadlocks are retried, anything else fails at once.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    max_attempts: u32,
+}
+
+impl RetryPolicy {
+    pub fn new(initial: Duration, max: Duration, max_attempts: u32) -> Self {
+        Self { backoff: Backoff::new(initial, max), max_attempts: max_attempts.max(1) }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Full jitter, uniform between zero and the exponential delay,
+    /// so orders which collided once don't collide again on the retry.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.backoff.delay(attempt);
+        rand::thread_rng().gen_range(Duration::ZERO..=delay)
+    }
+
+    pub(crate) async fn run<T, F, Fut>(&self, operation: F) -> core::result::Result<T, GaveUp>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.run_while(operation, Error::is_serialization_failure).await
+    }
+
+    async fn run_while<T, F, Fut>(
+        &self,
+        mut operation: F,
+        retryable: impl Fn(&Error) -> bool,
+    ) -> core::result::Result<T, GaveUp>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) if retryable(&e) && attempt < self.max_attempts => {
+                    let delay = self.delay(attempt - 1);
+                    info!("attempt {} failed, retrying in {:?}: {}", attempt, delay, e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(error) => return Err(GaveUp { error, attempts: attempt }),
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Stays well below the stage timeout of `OrderTask`.
+    fn default() -> Self {
+        Self::new(Duration::from_millis(50), Duration::from_secs(1), 5)
+    }
+}
+
+/// The last error of an update which is not retried anymore.
+#[derive(Debug)]
+pub(crate) struct GaveUp {
+    pub(crate) error: Error,
+    pub(crate) attempts: u32,
+}
+
+impl GaveUp {
+    /// Settles the order the update was for: a shortage fails it, a broken update dead-letters it,
+    /// an illegal move means somebody else already settled it.
+    pub(crate) async fn settle(&self, app_context: &ModelManager, order_id: i64, stage: &str) {
+        let settled = match &self.error {
+            Error::InsufficientStock { .. } => {
+                fail_order(app_context, order_id, stage, &self.error.to_string()).await
+            }
+            e if e.is_order_rejection() => return,
+            e => {
+                warn!("Dead-lettering order {} after {} attempts: {}", order_id, self.attempts, e);
+                dead_letter_order(app_context, order_id, stage, self.attempts, &e.to_string()).await
+            }
+        };
+        if let Err(e) = settled {
+            warn!("Failed to settle order {}: {:#?}", order_id, e)
+        }
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_delay_is_jittered_below_backoff() {
+        let policy = RetryPolicy::new(Duration::from_millis(10), Duration::from_millis(40), 5);
+        for attempt in 0..6 {
+            assert!(policy.delay(attempt) <= Duration::from_millis(40));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_attempts_are_bounded() {
+        let policy = RetryPolicy::new(Duration::from_millis(1), Duration::from_millis(1), 3);
+        let calls = AtomicU32::new(0);
+        let transient = |e: &Error| matches!(e, Error::CoreError);
+
+        let gave_up = policy.run_while(|| async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(Error::CoreError)
+        }, transient).await.unwrap_err();
+        assert_eq!(3, gave_up.attempts);
+        assert_eq!(3, calls.load(Ordering::SeqCst));
+
+        // anything else fails at once
+        let gave_up = policy.run_while(|| async {
+            Err::<(), _>(Error::WrongPassword)
+        }, transient).await.unwrap_err();
+        assert_eq!(1, gave_up.attempts);
+
+        let calls = AtomicU32::new(0);
+        let value = policy.run_while(|| async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(Error::CoreError),
+                _ => Ok(7),
+            }
+        }, transient).await.unwrap();
+        assert_eq!(7, value);
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::task::actor::{ActorReport, AnyActorHandle};
+use crate::task::supervisor::{TaskState, TaskStats};
+use crate::task::worker_pool::WorkerPoolMetrics;
+
+/// Upper bound for asking a task for its report, a busy or restarting task answers late.
+pub const STATUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Runtime snapshot of a supervised task, as reported by the `admin_tasks` RPC.
+#[derive(Clone, Debug, Serialize)]
+pub struct TaskStatus {
+    name: &'static str,
+    state: TaskState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uptime_ms: Option<u128>,
+    restarts: u64,
+    queue_depth: usize,
+    queue_capacity: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    in_flight_orders: Vec<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    worker_pool: Option<WorkerPoolMetrics>,
+}
+
+impl TaskStatus {
+    pub(crate) async fn collect(handle: &dyn AnyActorHandle, stats: &TaskStats) -> Self {
+        let report = match tokio::time::timeout(STATUS_TIMEOUT, handle.report()).await {
+            Ok(Ok(report)) => report,
+            Ok(Err(e)) => {
+                warn!("Failed to get report of {}: {:#}", handle.name(), e);
+                ActorReport::default()
+            }
+            Err(_) => {
+                warn!("{} did not report within {:?}", handle.name(), STATUS_TIMEOUT);
+                ActorReport::default()
+            }
+        };
+
+        Self {
+            name: handle.name(),
+            state: stats.state(),
+            uptime_ms: stats.uptime().map(|uptime| uptime.as_millis()),
+            restarts: stats.restarts(),
+            queue_depth: handle.mailbox_depth(),
+            queue_capacity: handle.mailbox_capacity(),
+            last_error: stats.last_error(),
+            in_flight_orders: report.in_flight_orders,
+            worker_pool: report.worker_pool,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state(&self) -> TaskState {
+        self.state
+    }
+
+    pub fn uptime_ms(&self) -> Option<u128> {
+        self.uptime_ms
+    }
+
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth
+    }
+
+    pub fn queue_capacity(&self) -> usize {
+        self.queue_capacity
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    pub fn in_flight_orders(&self) -> &Vec<i64> {
+        &self.in_flight_orders
+    }
+
+    pub fn worker_pool(&self) -> Option<&WorkerPoolMetrics> {
+        self.worker_pool.as_ref()
+    }
+}
@@ -1,79 +1,80 @@
 use std::sync::Arc;
-use std::time::Duration;
 
 use anyhow::Result;
-use tokio::select;
-use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::oneshot;
-use tracing::{info, instrument};
+use tracing::{error, info, instrument, Instrument};
 
 use lib_dto::order::OrderStatus::ReadyToDeliver;
 use lib_dto::order::OrderStored;
 
 use crate::bmc::general::update_storage_and_order;
-use crate::bmc::storage::UpdateType::Add;
+use crate::bmc::storage::UpdateType::Reserve;
 use crate::context::app_context::ModelManager;
-use crate::select_cancel;
-use crate::task::storage::StorageResponse::HealthOk;
+use crate::error::Error;
+use crate::task::actor::{Actor, ActorReport};
+use crate::task::in_flight::InFlight;
+use crate::task::retry::GaveUp;
+use crate::telemetry::order_span;
 
 #[derive(Debug)]
 pub enum StorageRequest {
-    Health(oneshot::Sender<StorageResponse>),
     UpdateStorage(OrderStored, oneshot::Sender<StorageResponse>),
+    Abort(i64),
 }
 
 #[derive(Debug)]
 pub enum StorageResponse {
-    HealthOk,
     Updated,
-    FailedToUpdate(OrderStored)
+    /// The order was already moved on, by a cancellation or another submission.
+    Skipped,
+    FailedToUpdate(OrderStored),
 }
 
-pub(crate) struct StorageTask {}
+pub(crate) struct StorageTask {
+    app_context: Arc<ModelManager>,
+    // in-flight updates are tracked instead of racing the cancellation token,
+    // so a shutdown can wait for their transactions to finish
+    in_flight: InFlight,
+}
 
-impl StorageTask {
-    pub(crate) fn start(
-        app_context: Arc<ModelManager>,
-    ) -> Sender<StorageRequest> {
-        let (tx, rx) = tokio::sync::mpsc::channel(64);
+impl Actor for StorageTask {
+    type Request = StorageRequest;
+    type Event = ();
 
-        let cancellation_token = app_context.cancellation_token();
-        tokio::spawn(async move {
-            select! {
-                _ = handle_storage_requests(app_context, rx) => {}
-                _ = cancellation_token.cancelled() => {
-                    info!("Cancelled by cancellation token.")
-                }
-            }
-        });
+    const NAME: &'static str = "StorageTask";
 
-        tx.clone()
+    async fn create(app_context: Arc<ModelManager>) -> Result<Self> {
+        Ok(Self { app_context, in_flight: InFlight::default() })
     }
-}
-
-#[instrument(skip_all)]
-pub async fn handle_storage_requests(
-    app_context: Arc<ModelManager>,
-    mut storage_rx: Receiver<StorageRequest>,
-) -> Result<()> {
-    let cancellation_token = app_context.cancellation_token();
 
-    while let Some(request) = storage_rx.recv().await {
+    #[instrument(skip_all)]
+    async fn handle(&mut self, request: StorageRequest) -> Result<()> {
         info!("Got storage request: {:#?}", &request);
+        self.in_flight.reap();
 
-        let cancellation_token_cloned = cancellation_token.clone();
-        let app_context_cloned = app_context.clone();
         match request {
-            StorageRequest::Health(tx) => {
-                tx.send(HealthOk).unwrap()
-            }
             StorageRequest::UpdateStorage(order, tx) => {
-                select_cancel!(handle_storage(app_context_cloned, order, tx), cancellation_token_cloned);
+                let span = order_span(Self::NAME, &order);
+                self.in_flight.spawn(order.order_id(), handle_storage(self.app_context.clone(), order, tx).instrument(span));
+            }
+            StorageRequest::Abort(order_id) => {
+                if self.in_flight.abort(order_id) {
+                    info!("Aborted storage update of order {}", order_id);
+                }
             }
         }
+
+        Ok(())
     }
 
-    Ok(())
+    fn report(&mut self) -> ActorReport {
+        self.in_flight.reap();
+        ActorReport { in_flight_orders: self.in_flight.order_ids(), ..Default::default() }
+    }
+
+    async fn drain(&mut self) {
+        self.in_flight.join_all().await;
+    }
 }
 
 #[instrument(skip_all)]
@@ -83,24 +84,20 @@ pub async fn handle_storage(
     response_tx: oneshot::Sender<StorageResponse>
 ) {
     info!("updating storage for order: {:#?}", &order);
-    select! {
-        _ = update_with_retry(app_context, order.clone()) => {
-            response_tx.send(StorageResponse::Updated).unwrap();
-        }
-        _ = tokio::time::sleep(Duration::from_secs(3)) => {
-            response_tx.send(StorageResponse::FailedToUpdate(order)).unwrap();
+    let retry = &app_context.app_config().update_retry;
+    let updated = retry.run(|| {
+        update_storage_and_order(&app_context, &order, Reserve, ReadyToDeliver, StorageTask::NAME, None, false)
+    }).await;
+    let response = match updated {
+        Ok(()) => StorageResponse::Updated,
+        Err(GaveUp { error: Error::IllegalTransition { .. }, .. }) => StorageResponse::Skipped,
+        Err(gave_up) => {
+            error!("Storage was not updated for order {}: {}", order.order_id(), gave_up.error);
+            gave_up.settle(&app_context, order.order_id(), StorageTask::NAME).await;
+            StorageResponse::FailedToUpdate(order)
         }
+    };
+    if response_tx.send(response).is_err() {
+        error!("failed to send storage response")
     }
 }
-
-#[instrument(skip_all)]
-async fn update_with_retry(
-    app_context: Arc<ModelManager>,
-    order: OrderStored,
-)  {
-    while let Err(e) = update_storage_and_order(app_context.clone(), &order, Add, ReadyToDeliver).await {
-        info!("delivery retrying update storage for order is: {:#?} because of {:#?}", &order, e);
-        //sleep(Duration::from_millis(700)).await;
-    }
-}
-
@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::select;
+use tokio::sync::mpsc::Receiver;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Receiver shared between restarts of the same task, so that senders handed out
+/// before a restart keep delivering to the new instance.
+pub(crate) type SharedReceiver<T> = Arc<tokio::sync::Mutex<Receiver<T>>>;
+
+pub(crate) fn shared_channel<T>(capacity: usize) -> (tokio::sync::mpsc::Sender<T>, SharedReceiver<T>) {
+    let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+    (tx, Arc::new(tokio::sync::Mutex::new(rx)))
+}
+
+#[derive(Clone, Debug)]
+pub enum RestartPolicy {
+    /// Restart whenever the task exits, even if it finished without an error.
+    Always,
+    /// Restart only when the task returned an error or panicked.
+    OnFailure,
+    /// Restart on failure, but give up after `max_restarts` restarts within `window`.
+    Limited { max_restarts: usize, window: Duration },
+}
+
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+
+    /// Doubles the initial delay for every consecutive restart, capped at `max`.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        self.initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max)
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(10))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TaskSpec {
+    name: &'static str,
+    policy: RestartPolicy,
+    backoff: Backoff,
+}
+
+impl TaskSpec {
+    pub fn new(name: &'static str, policy: RestartPolicy, backoff: Backoff) -> Self {
+        Self { name, policy, backoff }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Waiting for the backoff delay before the next start.
+    Restarting,
+    #[default]
+    Stopped,
+}
+
+#[derive(Debug, Default)]
+struct Lifecycle {
+    state: TaskState,
+    started_at: Option<Instant>,
+    last_error: Option<String>,
+}
+
+/// Written by the supervisor, read by anyone holding the `Arc`.
+#[derive(Debug, Default)]
+pub struct TaskStats {
+    restarts: AtomicU64,
+    lifecycle: Mutex<Lifecycle>,
+}
+
+impl TaskStats {
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    pub fn state(&self) -> TaskState {
+        self.lifecycle().state
+    }
+
+    /// Time since the current incarnation was started, `None` unless running.
+    pub fn uptime(&self) -> Option<Duration> {
+        let lifecycle = self.lifecycle();
+        match lifecycle.state {
+            TaskState::Running => lifecycle.started_at.map(|started_at| started_at.elapsed()),
+            _ => None,
+        }
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.lifecycle().last_error.clone()
+    }
+
+    fn lifecycle(&self) -> std::sync::MutexGuard<'_, Lifecycle> {
+        // the lock is never held across a panic point, recover the data anyway
+        self.lifecycle.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn set_running(&self) {
+        let mut lifecycle = self.lifecycle();
+        lifecycle.state = TaskState::Running;
+        lifecycle.started_at = Some(Instant::now());
+    }
+
+    fn set_state(&self, state: TaskState) {
+        self.lifecycle().state = state;
+    }
+
+    fn set_last_error(&self, error: String) {
+        self.lifecycle().last_error = Some(error);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TaskRestarts {
+    name: &'static str,
+    restarts: u64,
+}
+
+impl TaskRestarts {
+    pub fn new(name: &'static str, restarts: u64) -> Self {
+        Self { name, restarts }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+}
+
+/// Spawns a supervisor which starts the task with `start` and restarts it according to
+/// the policy in `spec` until the cancellation token fires.
+pub(crate) fn supervise<F>(
+    spec: TaskSpec,
+    stats: Arc<TaskStats>,
+    cancellation_token: CancellationToken,
+    start: F,
+) -> JoinHandle<()>
+where
+    F: Fn() -> JoinHandle<Result<()>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut restarts_in_window: VecDeque<Instant> = VecDeque::new();
+        let mut attempt: u32 = 0;
+
+        loop {
+            info!("Starting {}", spec.name);
+            let started_at = Instant::now();
+            let join_handle = start();
+            stats.set_running();
+
+            let outcome = select! {
+                outcome = join_handle => outcome,
+                _ = cancellation_token.cancelled() => {
+                    info!("Supervisor of {} cancelled by cancellation token.", spec.name);
+                    stats.set_state(TaskState::Stopped);
+                    return;
+                }
+            };
+
+            let failed = match outcome {
+                Ok(Ok(())) => {
+                    info!("{} finished", spec.name);
+                    false
+                }
+                Ok(Err(e)) => {
+                    error!("{} failed: {:#?}", spec.name, e);
+                    stats.set_last_error(format!("{:#}", e));
+                    true
+                }
+                Err(e) => {
+                    error!("{} panicked: {:#?}", spec.name, e);
+                    stats.set_last_error(e.to_string());
+                    true
+                }
+            };
+
+            if cancellation_token.is_cancelled() {
+                stats.set_state(TaskState::Stopped);
+                return;
+            }
+
+            if !should_restart(&spec.policy, failed, &mut restarts_in_window) {
+                warn!("{} will not be restarted", spec.name);
+                stats.set_state(TaskState::Stopped);
+                return;
+            }
+            stats.set_state(TaskState::Restarting);
+
+            // a task that stayed up for a while starts over with the shortest delay
+            if started_at.elapsed() > spec.backoff.max {
+                attempt = 0;
+            }
+            let delay = spec.backoff.delay(attempt);
+            attempt = attempt.saturating_add(1);
+
+            info!("Restarting {} in {:?}", spec.name, delay);
+            select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = cancellation_token.cancelled() => {
+                    info!("Supervisor of {} cancelled by cancellation token.", spec.name);
+                    stats.set_state(TaskState::Stopped);
+                    return;
+                }
+            }
+            stats.restarts.fetch_add(1, Ordering::Relaxed);
+        }
+    })
+}
+
+fn should_restart(
+    policy: &RestartPolicy,
+    failed: bool,
+    restarts_in_window: &mut VecDeque<Instant>,
+) -> bool {
+    match policy {
+        RestartPolicy::Always => true,
+        RestartPolicy::OnFailure => failed,
+        RestartPolicy::Limited { max_restarts, window } => {
+            if !failed {
+                return false;
+            }
+            let now = Instant::now();
+            while let Some(restarted_at) = restarts_in_window.front() {
+                if now.duration_since(*restarted_at) > *window {
+                    restarts_in_window.pop_front();
+                } else {
+                    break;
+                }
+            }
+            if restarts_in_window.len() >= *max_restarts {
+                return false;
+            }
+            restarts_in_window.push_back(now);
+            true
+        }
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use anyhow::bail;
+
+    use super::*;
+
+    fn fast_backoff() -> Backoff {
+        Backoff::new(Duration::from_millis(1), Duration::from_millis(5))
+    }
+
+    #[test]
+    fn test_backoff_delay_is_capped() {
+        let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(1));
+
+        assert_eq!(backoff.delay(0), Duration::from_millis(100));
+        assert_eq!(backoff.delay(2), Duration::from_millis(400));
+        assert_eq!(backoff.delay(10), Duration::from_secs(1));
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_supervise_restarts_panicked_task() {
+        let spec = TaskSpec::new("test", RestartPolicy::OnFailure, fast_backoff());
+        let stats = Arc::new(TaskStats::default());
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let runs_cloned = runs.clone();
+        let supervisor = supervise(spec, stats.clone(), CancellationToken::new(), move || {
+            let runs = runs_cloned.clone();
+            tokio::spawn(async move {
+                if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("test panic");
+                }
+                Ok(())
+            })
+        });
+
+        supervisor.await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        assert_eq!(stats.restarts(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_supervise_limited_gives_up() {
+        let policy = RestartPolicy::Limited { max_restarts: 2, window: Duration::from_secs(60) };
+        let spec = TaskSpec::new("test", policy, fast_backoff());
+        let stats = Arc::new(TaskStats::default());
+
+        let supervisor = supervise(spec, stats.clone(), CancellationToken::new(), || {
+            tokio::spawn(async { bail!("test error") })
+        });
+
+        supervisor.await.unwrap();
+        assert_eq!(stats.restarts(), 2);
+        assert_eq!(stats.state(), TaskState::Stopped);
+        assert_eq!(stats.last_error().as_deref(), Some("test error"));
+        assert!(stats.uptime().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shared_receiver_survives_restart() {
+        let (tx, rx) = shared_channel::<u32>(4);
+        let spec = TaskSpec::new("test", RestartPolicy::OnFailure, fast_backoff());
+        let stats = Arc::new(TaskStats::default());
+        let received = Arc::new(AtomicUsize::new(0));
+
+        let received_cloned = received.clone();
+        let supervisor = supervise(spec, stats.clone(), CancellationToken::new(), move || {
+            let rx = rx.clone();
+            let received = received_cloned.clone();
+            tokio::spawn(async move {
+                let mut rx = rx.lock().await;
+                while let Some(value) = rx.recv().await {
+                    received.fetch_add(1, Ordering::SeqCst);
+                    if value == 0 {
+                        panic!("test panic");
+                    }
+                }
+                Ok(())
+            })
+        });
+
+        tx.send(0).await.unwrap();
+        tx.send(1).await.unwrap();
+        drop(tx);
+
+        supervisor.await.unwrap();
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+        assert_eq!(stats.restarts(), 1);
+    }
+}
+// endregion: --- Tests
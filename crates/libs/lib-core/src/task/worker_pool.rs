@@ -0,0 +1,143 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+/// Bounds how many orders are processed at once and measures how much of that bound is used.
+pub(crate) struct WorkerPool {
+    semaphore: Arc<Semaphore>,
+    workers: usize,
+    counters: Arc<Counters>,
+    started_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    active: AtomicUsize,
+    peak_active: AtomicUsize,
+    processed: AtomicU64,
+    contended: AtomicU64,
+    busy_ms: AtomicU64,
+}
+
+/// A busy worker, it is given back to the pool when dropped.
+pub(crate) struct Worker {
+    _permit: OwnedSemaphorePermit,
+    counters: Arc<Counters>,
+    started_at: Instant,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WorkerPoolMetrics {
+    workers: usize,
+    active: usize,
+    peak_active: usize,
+    processed: u64,
+    /// Orders which had to wait for another order holding one of their books.
+    contended: u64,
+    /// Average number of busy workers since the pool was created.
+    avg_parallelism: f64,
+}
+
+impl WorkerPool {
+    pub(crate) fn new(workers: usize) -> Self {
+        let workers = workers.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(workers)),
+            workers,
+            counters: Arc::new(Counters::default()),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Waits for a free worker.
+    pub(crate) async fn acquire(&self) -> Worker {
+        let permit = self.semaphore.clone().acquire_owned().await
+            .expect("worker pool semaphore is never closed");
+        let active = self.counters.active.fetch_add(1, Ordering::Relaxed) + 1;
+        self.counters.peak_active.fetch_max(active, Ordering::Relaxed);
+        Worker { _permit: permit, counters: self.counters.clone(), started_at: Instant::now() }
+    }
+
+    pub(crate) fn metrics(&self) -> WorkerPoolMetrics {
+        let elapsed_ms = self.started_at.elapsed().as_millis().max(1) as f64;
+        WorkerPoolMetrics {
+            workers: self.workers,
+            active: self.counters.active.load(Ordering::Relaxed),
+            peak_active: self.counters.peak_active.load(Ordering::Relaxed),
+            processed: self.counters.processed.load(Ordering::Relaxed),
+            contended: self.counters.contended.load(Ordering::Relaxed),
+            avg_parallelism: self.counters.busy_ms.load(Ordering::Relaxed) as f64 / elapsed_ms,
+        }
+    }
+}
+
+impl Worker {
+    pub(crate) fn contended(&self) {
+        self.counters.contended.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.counters.active.fetch_sub(1, Ordering::Relaxed);
+        self.counters.processed.fetch_add(1, Ordering::Relaxed);
+        self.counters.busy_ms.fetch_add(self.started_at.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl WorkerPoolMetrics {
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    pub fn peak_active(&self) -> usize {
+        self.peak_active
+    }
+
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+
+    pub fn contended(&self) -> u64 {
+        self.contended
+    }
+
+    pub fn avg_parallelism(&self) -> f64 {
+        self.avg_parallelism
+    }
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pool_is_bounded() {
+        let pool = WorkerPool::new(2);
+
+        let first = pool.acquire().await;
+        let second = pool.acquire().await;
+        assert_eq!(pool.metrics().active(), 2);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(20), pool.acquire()).await.is_err());
+
+        second.contended();
+        drop(first);
+        drop(second);
+        let _third = pool.acquire().await;
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.active(), 1);
+        assert_eq!(metrics.peak_active(), 2);
+        assert_eq!(metrics.processed(), 2);
+        assert_eq!(metrics.contended(), 1);
+    }
+}
+// endregion: --- Tests
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use lib_dto::order::OrderStored;
+
+/// Name of the W3C trace context header, in Kafka headers as well.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// The traceparent of the current span, none unless the OpenTelemetry layer is installed.
+pub fn current_traceparent() -> Option<String> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Span::current().context(), &mut carrier);
+    carrier.remove(TRACEPARENT)
+}
+
+/// Continues the trace of `traceparent` in `span`, a missing or malformed one leaves the span a root.
+pub fn set_parent(span: &Span, traceparent: Option<&str>) {
+    let Some(traceparent) = traceparent else {
+        return;
+    };
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    span.set_parent(TraceContextPropagator::new().extract(&carrier));
+}
+
+/// Span of a pipeline stage working on `order`, part of the trace of the request which created it.
+pub(crate) fn order_span(stage: &'static str, order: &OrderStored) -> Span {
+    let span = info_span!("order", stage, order_id = order.order_id());
+    set_parent(&span, order.traceparent());
+    span
+}
+
+// region:    --- Tests
+#[cfg(test)]
+mod tests {
+    use opentelemetry::sdk::trace::TracerProvider;
+    use opentelemetry::trace::TracerProvider as _;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_continue_trace() {
+        // the tracer only holds a weak reference to its provider
+        let provider = TracerProvider::builder().build();
+        let tracer = provider.tracer("test");
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(None, current_traceparent());
+
+            let span = info_span!("consume");
+            set_parent(&span, Some(PARENT));
+            let traceparent = span.in_scope(current_traceparent).expect("must be traced");
+            // same trace, new span
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            assert_ne!(PARENT, traceparent);
+        });
+    }
+
+    #[test]
+    fn test_untraced_without_layer() {
+        let span = info_span!("consume");
+        set_parent(&span, Some(PARENT));
+        assert_eq!(None, span.in_scope(current_traceparent));
+    }
+}
+// endregion: --- Tests
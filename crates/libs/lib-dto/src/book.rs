@@ -2,8 +2,14 @@ use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+use crate::order::OrderItem;
+
 #[derive(Debug, Deserialize, Serialize, Builder, FromRow)]
 pub struct BookInfo {
+    /// Set on the stored books, ignored when a book is created or imported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    pub id: Option<i64>,
     pub title: String,
     pub author: Option<String>,
     pub isbn: String,
@@ -12,7 +18,41 @@ pub struct BookInfo {
 
 impl BookInfo {
     pub fn new(title: String, author: Option<String>, isbn: String, description: String) -> Self {
-        Self { title, author, isbn, description }
+        Self { id: None, title, author, isbn, description }
+    }
+
+    pub fn with_id(mut self, id: i64) -> Self {
+        self.id = Some(id);
+        self
+    }
+}
+
+/// Identifies a book by its id or its ISBN, `{"book_id": 1}` or `{"isbn": "..."}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookKey {
+    BookId(i64),
+    Isbn(String),
+}
+
+/// Outcome of an import, books are matched by ISBN.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct BookImportReport {
+    created: usize,
+    updated: usize,
+}
+
+impl BookImportReport {
+    pub fn new(created: usize, updated: usize) -> Self {
+        Self { created, updated }
+    }
+
+    pub fn created(&self) -> usize {
+        self.created
+    }
+
+    pub fn updated(&self) -> usize {
+        self.updated
     }
 }
 
@@ -50,6 +90,7 @@ impl BookDescription {
 pub struct BookStorageInfo {
     id: i64,
     quantity: Option<i64>,
+    reserved: Option<i64>,
 }
 
 impl BookStorageInfo {
@@ -60,7 +101,41 @@ impl BookStorageInfo {
     pub fn quantity(&self) -> Option<i64> {
         self.quantity
     }
+
+    pub fn reserved(&self) -> Option<i64> {
+        self.reserved
+    }
 }
 
+/// Stock to add to the storage, per book.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StockList {
+    stock: Vec<OrderItem>,
+}
 
+impl StockList {
+    pub fn new(stock: Vec<OrderItem>) -> Self {
+        Self { stock }
+    }
+
+    pub fn stock(&self) -> &Vec<OrderItem> {
+        &self.stock
+    }
+}
 
+/// How many copies of a book were delivered.
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct BookSales {
+    book_id: i64,
+    sold: i64,
+}
+
+impl BookSales {
+    pub fn book_id(&self) -> i64 {
+        self.book_id
+    }
+
+    pub fn sold(&self) -> i64 {
+        self.sold
+    }
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::order::{OrderStatus, OrderStored};
+
+/// Version of the `OrderEvent` layout, bumped on every incompatible change of it or its payload.
+pub const ORDER_EVENT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderEventType {
+    OrderDelivered,
+    OrderCancelled,
+    OrderFailed,
+    /// Any other change of the order.
+    OrderUpdated,
+}
+
+impl OrderEventType {
+    pub fn for_status(status: &OrderStatus) -> Self {
+        match status {
+            OrderStatus::Delivered => Self::OrderDelivered,
+            OrderStatus::Cancelled => Self::OrderCancelled,
+            OrderStatus::Failed => Self::OrderFailed,
+            _ => Self::OrderUpdated,
+        }
+    }
+}
+
+/// Envelope of the order events published to Kafka, keyed by the order id.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OrderEvent {
+    event_id: Uuid,
+    event_type: OrderEventType,
+    schema_version: u32,
+    occurred_at: DateTime<Utc>,
+    payload: OrderStored,
+}
+
+impl OrderEvent {
+    /// The event for the current state of `order`, its type follows the status.
+    pub fn new(order: OrderStored) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            event_type: OrderEventType::for_status(order.status()),
+            schema_version: ORDER_EVENT_SCHEMA_VERSION,
+            occurred_at: order.updated_at(),
+            payload: order,
+        }
+    }
+
+    pub fn event_id(&self) -> Uuid {
+        self.event_id
+    }
+
+    pub fn event_type(&self) -> &OrderEventType {
+        &self.event_type
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    pub fn occurred_at(&self) -> DateTime<Utc> {
+        self.occurred_at
+    }
+
+    pub fn payload(&self) -> &OrderStored {
+        &self.payload
+    }
+
+    pub fn order_id(&self) -> i64 {
+        self.payload.order_id()
+    }
+}
+
+/// How many dead-lettered order events to move back to the order topic.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeadLetterReplay {
+    limit: usize,
+}
+
+impl DeadLetterReplay {
+    pub fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+/// Selects the orders whose events are published again, every bound is optional and inclusive.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct OrderRepublish {
+    status: Option<OrderStatus>,
+    created_from: Option<DateTime<Utc>>,
+    created_to: Option<DateTime<Utc>>,
+    updated_from: Option<DateTime<Utc>>,
+    updated_to: Option<DateTime<Utc>>,
+    /// Only counts the matching orders.
+    #[serde(default)]
+    dry_run: bool,
+    /// Events per second, unlimited if none.
+    rate_limit: Option<u32>,
+}
+
+impl OrderRepublish {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_status(mut self, status: OrderStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn with_created(mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Self {
+        self.created_from = from;
+        self.created_to = to;
+        self
+    }
+
+    pub fn with_updated(mut self, from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>) -> Self {
+        self.updated_from = from;
+        self.updated_to = to;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: u32) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn status(&self) -> Option<&OrderStatus> {
+        self.status.as_ref()
+    }
+
+    pub fn created_from(&self) -> Option<DateTime<Utc>> {
+        self.created_from
+    }
+
+    pub fn created_to(&self) -> Option<DateTime<Utc>> {
+        self.created_to
+    }
+
+    pub fn updated_from(&self) -> Option<DateTime<Utc>> {
+        self.updated_from
+    }
+
+    pub fn updated_to(&self) -> Option<DateTime<Utc>> {
+        self.updated_to
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn rate_limit(&self) -> Option<u32> {
+        self.rate_limit
+    }
+}
+
+/// Outcome of an `OrderRepublish`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct OrderRepublishReport {
+    matched: i64,
+    /// Events written to the outbox, the producer relays them.
+    republished: i64,
+    dry_run: bool,
+}
+
+impl OrderRepublishReport {
+    pub fn new(matched: i64, republished: i64, dry_run: bool) -> Self {
+        Self { matched, republished, dry_run }
+    }
+
+    pub fn matched(&self) -> i64 {
+        self.matched
+    }
+
+    pub fn republished(&self) -> i64 {
+        self.republished
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
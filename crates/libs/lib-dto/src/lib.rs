@@ -1,3 +1,5 @@
 pub mod book;
+pub mod event;
 pub mod order;
+pub mod outbox;
 pub mod user;
@@ -9,7 +9,9 @@ pub enum OrderStatus {
     New,
     InProgress,
     ReadyToDeliver,
-    Delivered
+    Delivered,
+    Cancelled,
+    Failed,
 }
 
 #[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
@@ -20,6 +22,10 @@ pub struct OrderStored {
     status: OrderStatus,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
+    // travels in the Kafka headers, not in the payload
+    #[sqlx(default)]
+    #[serde(skip)]
+    traceparent: Option<String>,
 }
 
 impl OrderStored {
@@ -46,6 +52,11 @@ impl OrderStored {
     pub fn updated_at(&self) -> DateTime<Utc> {
         self.updated_at
     }
+
+    /// W3C traceparent of the request which created the order.
+    pub fn traceparent(&self) -> Option<&str> {
+        self.traceparent.as_deref()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -141,4 +152,109 @@ impl OrderId {
     pub fn order_id(&self) -> i64 {
         self.order_id
     }
-}
\ No newline at end of file
+}
+
+/// Asks for the order once its status is no longer `status`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderWatch {
+    order_id: i64,
+    status: OrderStatus,
+}
+
+impl OrderWatch {
+    pub fn new(order_id: i64, status: OrderStatus) -> Self {
+        Self { order_id, status }
+    }
+
+    pub fn order_id(&self) -> i64 {
+        self.order_id
+    }
+
+    pub fn status(&self) -> &OrderStatus {
+        &self.status
+    }
+}
+
+/// One transition of an order, `from_status` is empty for the creation of the order.
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize)]
+pub struct OrderStatusChange {
+    id: i64,
+    order_id: i64,
+    from_status: Option<OrderStatus>,
+    to_status: OrderStatus,
+    actor: String,
+    reason: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl OrderStatusChange {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn order_id(&self) -> i64 {
+        self.order_id
+    }
+
+    pub fn from_status(&self) -> Option<&OrderStatus> {
+        self.from_status.as_ref()
+    }
+
+    pub fn to_status(&self) -> &OrderStatus {
+        &self.to_status
+    }
+
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+/// A dead-lettered order, `requeued_at` is set once an admin sent it through the pipeline again.
+#[derive(Clone, Debug, FromRow, Serialize, Deserialize)]
+pub struct FailedOrder {
+    id: i64,
+    order_id: i64,
+    stage: String,
+    attempts: i32,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+    requeued_at: Option<DateTime<Utc>>,
+}
+
+impl FailedOrder {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn order_id(&self) -> i64 {
+        self.order_id
+    }
+
+    pub fn stage(&self) -> &str {
+        &self.stage
+    }
+
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+
+    pub fn last_error(&self) -> &str {
+        &self.last_error
+    }
+
+    pub fn failed_at(&self) -> DateTime<Utc> {
+        self.failed_at
+    }
+
+    pub fn requeued_at(&self) -> Option<DateTime<Utc>> {
+        self.requeued_at
+    }
+}
@@ -0,0 +1,97 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::FromRow;
+
+#[derive(Clone, Debug)]
+pub struct OutboxForCreate {
+    topic: String,
+    message_key: String,
+    payload: Value,
+    traceparent: Option<String>,
+}
+
+impl OutboxForCreate {
+    pub fn new(topic: impl Into<String>, message_key: impl Into<String>, payload: Value) -> Self {
+        Self { topic: topic.into(), message_key: message_key.into(), payload, traceparent: None }
+    }
+
+    /// Continues the trace of `traceparent` in the consumers of the message.
+    pub fn with_traceparent(mut self, traceparent: Option<String>) -> Self {
+        self.traceparent = traceparent;
+        self
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn message_key(&self) -> &str {
+        &self.message_key
+    }
+
+    pub fn payload(&self) -> &Value {
+        &self.payload
+    }
+
+    pub fn traceparent(&self) -> Option<&str> {
+        self.traceparent.as_deref()
+    }
+}
+
+#[derive(Clone, FromRow, Debug, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    id: i64,
+    topic: String,
+    message_key: String,
+    payload: sqlx::types::Json<Value>,
+    traceparent: Option<String>,
+    attempts: i32,
+    last_error: Option<String>,
+    next_attempt_at: DateTime<Utc>,
+    sent_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+impl OutboxMessage {
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn message_key(&self) -> &str {
+        &self.message_key
+    }
+
+    pub fn payload(&self) -> &Value {
+        &self.payload
+    }
+
+    pub fn traceparent(&self) -> Option<&str> {
+        self.traceparent.as_deref()
+    }
+
+    /// Number of publish attempts, including the one in progress.
+    pub fn attempts(&self) -> i32 {
+        self.attempts
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    pub fn next_attempt_at(&self) -> DateTime<Utc> {
+        self.next_attempt_at
+    }
+
+    pub fn sent_at(&self) -> Option<DateTime<Utc>> {
+        self.sent_at
+    }
+
+    pub fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
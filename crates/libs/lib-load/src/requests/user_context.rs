@@ -11,6 +11,7 @@ use serde::Deserialize;
 use serde_json::{json, Value};
 use tracing::{debug, info};
 
+use lib_dto::book::StockList;
 use lib_dto::user::{AuthCode, UserForCreate, UserForSignIn};
 use lib_utils::constants::{AUTH_SOCKET_ADDR, WEB_SOCKET_ADDR};
 use lib_utils::json::result;
@@ -70,6 +71,13 @@ impl UserContext {
         self.post("/api/rpc", request).await;
     }
 
+    /// Only works for a phone listed in `ADMIN_PHONES`.
+    pub async fn restock(&mut self, stock_list: StockList) {
+        info!("Restocking books");
+        let request = request("restock_books", Some(stock_list));
+        self.post("/api/rpc", request).await;
+    }
+
     pub async fn create_user(&mut self, user_body: &UserForCreate) -> Response<Incoming> {
         self.post("/sign-up", json!(user_body)).await
     }
@@ -1,10 +1,11 @@
 use std::sync::Arc;
 
-use axum::{Router, routing::post};
+use axum::{Router, routing::{get, post}};
 
 use lib_core::context::app_context::ModelManager;
 
 use crate::handlers::auth::{check_code, check_if_exists, sign_in, sign_up};
+use crate::handlers::health::{auth_live, auth_ready};
 
 pub async fn auth_app(app_context: Arc<ModelManager>) -> Router {
     Router::new()
@@ -12,6 +13,8 @@ pub async fn auth_app(app_context: Arc<ModelManager>) -> Router {
         .route("/sign-up", post(sign_up))
         .route("/sign-in", post(sign_in))
         .route("/check-code", post(check_code))
+        .route("/health/live", get(auth_live))
+        .route("/health/ready", get(auth_ready))
         .with_state(app_context)
 }
 
@@ -6,6 +6,7 @@ use std::fs::File;
 use std::io::BufReader;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
 
 use axum::{extract::{Json, State}, middleware, Router, routing::{get, post}};
 use axum::http::StatusCode;
@@ -18,8 +19,13 @@ use tower_cookies::{CookieManagerLayer, Cookies};
 use tracing::{debug, error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use lib_core::bmc::migration::MIGRATOR;
 use lib_core::context::app_context::{AppConfig, ModelManager};
+use lib_core::bus::{self, MessageBusConfig, DEFAULT_PARTITIONS};
+use lib_core::notify::source::OrderSourceConfig;
+use lib_core::task::kafka::{ConsumerOffsets, DeadLetterConfig, KafkaProducerConfig, KafkaTopicConfig};
 use lib_core::task::main_task::MainTaskRequest;
+use lib_core::task::retry::RetryPolicy;
 
 use crate::handlers::login::login;
 use crate::handlers::rpc::rpc;
@@ -27,14 +33,52 @@ use crate::middleware::mw_ctx::{mw_ctx_check, mw_ctx_create};
 use crate::middleware::mw_req_stamp::mw_req_stamp_resolver;
 use crate::middleware::mw_res_map::mw_response_map;
 
+const DEFAULT_SHUTDOWN_DEADLINE_SECS: u64 = 30;
+const DEFAULT_ORDER_WORKERS: usize = 8;
+const DEFAULT_UPDATE_RETRY_ATTEMPTS: u32 = 5;
+const DEFAULT_UPDATE_RETRY_INITIAL_MS: u64 = 50;
+const DEFAULT_UPDATE_RETRY_MAX_MS: u64 = 1000;
+const DEFAULT_STALE_ORDER_SECS: u64 = 60;
+const DEFAULT_ORDER_SOURCE_POLL_MS: u64 = 500;
+const DEFAULT_ORDER_SOURCE_TOPIC: &str = "order-source-topic";
+
 pub async fn create_app_context(main_tx: Sender<MainTaskRequest>) -> Arc<ModelManager> {
     let db_url = read_db_url("local.properties"); // todo from env
     let pool = get_pool(&db_url).await;
 
-    let kafka_url = env::var("KAFKA_URL").expect("must be ok");
+    let message_bus = message_bus();
+    let kafka_url = match message_bus {
+        MessageBusConfig::Kafka => env::var("KAFKA_URL").expect("KAFKA_URL is required by the kafka message bus"),
+        MessageBusConfig::InMemory { .. } => env::var("KAFKA_URL").unwrap_or_default(),
+    };
+    let shutdown_deadline = Duration::from_secs(env_or("SHUTDOWN_DEADLINE_SECS", DEFAULT_SHUTDOWN_DEADLINE_SECS));
+    let order_workers = env_or("ORDER_WORKERS", DEFAULT_ORDER_WORKERS);
+    let admin_phones = env::var("ADMIN_PHONES").unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|phone| !phone.is_empty())
+        .map(str::to_string)
+        .collect();
+    let update_retry = RetryPolicy::new(
+        Duration::from_millis(env_or("UPDATE_RETRY_INITIAL_MS", DEFAULT_UPDATE_RETRY_INITIAL_MS)),
+        Duration::from_millis(env_or("UPDATE_RETRY_MAX_MS", DEFAULT_UPDATE_RETRY_MAX_MS)),
+        env_or("UPDATE_RETRY_ATTEMPTS", DEFAULT_UPDATE_RETRY_ATTEMPTS),
+    );
+    let stale_order_after = Duration::from_secs(env_or("STALE_ORDER_SECS", DEFAULT_STALE_ORDER_SECS));
     let app_config: AppConfig = AppConfig {
         auth_url: Arc::new("http://127.0.0.1:3001".to_string()), //todo from env
         kafka_url: Arc::new(kafka_url),
+        shutdown_deadline,
+        order_workers,
+        admin_phones: Arc::new(admin_phones),
+        update_retry,
+        stale_order_after,
+        order_source: order_source(),
+        kafka_producer: kafka_producer(),
+        kafka_topics: kafka_topics(),
+        message_bus,
+        consumer_offsets: consumer_offsets(),
+        dead_letter: dead_letter(),
     };
 
     let app_context: Arc<ModelManager> = Arc::new(ModelManager::create(
@@ -42,11 +86,90 @@ pub async fn create_app_context(main_tx: Sender<MainTaskRequest>) -> Arc<ModelMa
         app_config,
         Arc::new(pool),
     ));
+    //Fail at once, a pipeline without its topics must not start
+    bus::provision_topics(&app_context).await.expect("failed to create the configured kafka topics");
 
     app_context
 }
 
-async fn get_client(db_url: &String) -> Client {
+/// `ORDER_SOURCE` is one of `listen` (the default), `poll` or `kafka`.
+fn order_source() -> OrderSourceConfig {
+    match env::var("ORDER_SOURCE").unwrap_or_default().as_str() {
+        "" | "listen" => OrderSourceConfig::Listen,
+        "poll" => OrderSourceConfig::Poll {
+            interval: Duration::from_millis(env_or("ORDER_SOURCE_POLL_MS", DEFAULT_ORDER_SOURCE_POLL_MS)),
+        },
+        "kafka" => OrderSourceConfig::Kafka {
+            topic: env::var("ORDER_SOURCE_TOPIC").unwrap_or_else(|_| DEFAULT_ORDER_SOURCE_TOPIC.to_string()),
+        },
+        other => panic!("unknown ORDER_SOURCE {}", other),
+    }
+}
+
+/// `MESSAGE_BUS` is `kafka` (the default) or `memory`, which needs no broker.
+fn message_bus() -> MessageBusConfig {
+    match env::var("MESSAGE_BUS").unwrap_or_default().as_str() {
+        "" | "kafka" => MessageBusConfig::Kafka,
+        "memory" => MessageBusConfig::InMemory {
+            partitions: env_or("MESSAGE_BUS_PARTITIONS", DEFAULT_PARTITIONS),
+        },
+        other => panic!("unknown MESSAGE_BUS {}", other),
+    }
+}
+
+/// `CONSUMER_OFFSETS` is `kafka` (the default) or `postgres`.
+fn consumer_offsets() -> ConsumerOffsets {
+    match env::var("CONSUMER_OFFSETS").unwrap_or_default().as_str() {
+        "" | "kafka" => ConsumerOffsets::Kafka,
+        "postgres" => ConsumerOffsets::Postgres,
+        other => panic!("unknown CONSUMER_OFFSETS {}", other),
+    }
+}
+
+/// Every setting falls back to `KafkaProducerConfig::default()`.
+fn kafka_producer() -> KafkaProducerConfig {
+    let default = KafkaProducerConfig::default();
+    KafkaProducerConfig {
+        topic: env::var("KAFKA_ORDER_TOPIC").unwrap_or(default.topic),
+        acks: env::var("KAFKA_ACKS").unwrap_or(default.acks),
+        enable_idempotence: env_or("KAFKA_IDEMPOTENCE", default.enable_idempotence),
+        linger: Duration::from_millis(env_or("KAFKA_LINGER_MS", default.linger.as_millis() as u64)),
+        compression: env::var("KAFKA_COMPRESSION").unwrap_or(default.compression),
+        max_in_flight: env_or("KAFKA_MAX_IN_FLIGHT", default.max_in_flight),
+    }
+}
+
+/// Every setting falls back to `KafkaTopicConfig::default()`.
+fn kafka_topics() -> KafkaTopicConfig {
+    let default = KafkaTopicConfig::default();
+    KafkaTopicConfig {
+        partitions: env_or("KAFKA_TOPIC_PARTITIONS", default.partitions),
+        replication_factor: env_or("KAFKA_TOPIC_REPLICATION_FACTOR", default.replication_factor),
+        retention: env_opt("KAFKA_TOPIC_RETENTION_MS").map(Duration::from_millis).or(default.retention),
+    }
+}
+
+/// Every setting falls back to `DeadLetterConfig::default()`.
+fn dead_letter() -> DeadLetterConfig {
+    let default = DeadLetterConfig::default();
+    DeadLetterConfig {
+        topic: env::var("DEAD_LETTER_TOPIC").unwrap_or(default.topic),
+        max_attempts: env_or("DEAD_LETTER_MAX_ATTEMPTS", default.max_attempts),
+    }
+}
+
+/// The setting `key` parsed as `T`, `default` if it is missing or malformed.
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env_opt(key).unwrap_or(default)
+}
+
+/// Like `env_or`, for the settings without a default.
+fn env_opt<T: std::str::FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok()
+        .and_then(|value| value.parse().ok())
+}
+
+async fn get_client(db_url: &str) -> Client {
     //Unwrap because if we can't connect we must fail at once
     let (client, connection) =
         tokio_postgres::connect(db_url, NoTls).await.unwrap();
@@ -62,14 +185,14 @@ async fn get_client(db_url: &String) -> Client {
     client
 }
 
-async fn get_pool(db_url: &String) -> Pool<Postgres> {
+async fn get_pool(db_url: &str) -> Pool<Postgres> {
     let pool = PgPoolOptions::new()
         .max_connections(5)
         .connect(db_url)
         .await
         .unwrap();
 
-    sqlx::migrate!("../../../db/migrations-auth").run(&pool).await.unwrap();
+    MIGRATOR.run(&pool).await.unwrap();
 
     pool
 }
@@ -3,11 +3,12 @@
 
 use std::sync::Arc;
 
-use axum::{middleware, Router, routing::post};
+use axum::{middleware, Router, routing::{get, post}};
 use tower_cookies::CookieManagerLayer;
 
 use lib_core::context::app_context::ModelManager;
 
+use crate::handlers::health::{live, ready};
 use crate::handlers::login::login;
 use crate::handlers::rpc::rpc;
 use crate::middleware::mw_ctx::{mw_ctx_check, mw_ctx_create};
@@ -22,6 +23,8 @@ pub async fn web_app(app_context: Arc<ModelManager>) -> Router {
     Router::new()
         .nest("/api", routes_rpc)
         .route("/login", post(login))
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
         .layer(middleware::map_response(mw_response_map))
         .layer(middleware::from_fn_with_state(app_context.clone(), mw_ctx_create))
         .layer(CookieManagerLayer::new())
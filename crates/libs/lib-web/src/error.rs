@@ -6,6 +6,8 @@ use axum::response::{IntoResponse, Response};
 use serde::Serialize;
 use tracing::{error, info};
 
+use lib_dto::order::OrderStatus;
+
 use crate::ctx::CtxExtError;
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -13,12 +15,21 @@ pub type Result<T> = core::result::Result<T, Error>;
 //#[serde_as]
 #[derive(Debug, Serialize, strum_macros::AsRefStr)]
 #[serde(tag = "type", content = "data")]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     WebError,
     Anyhow,
 
     UnauthorizedAccess,
 
+    IllegalOrderTransition { order_id: i64, from: OrderStatus, to: OrderStatus },
+    InvalidQuantity(String),
+    NotDeadLettered(i64),
+
+    BookNotFound(String),
+    BookConflict { field: &'static str, value: String },
+    InvalidBook(String),
+
     RpcRequestParsing,
     RpcNoParams,
     UnknownRpcMethod(String),
@@ -65,8 +76,16 @@ impl std::error::Error for Error {}
 // endregion: --- Error Boilerplate
 
 impl From<lib_core::error::Error> for Error {
-    fn from(_: lib_core::error::Error) -> Self {
-        Error::WebError
+    fn from(value: lib_core::error::Error) -> Self {
+        match value {
+            lib_core::error::Error::IllegalTransition { order_id, from, to } => {
+                Error::IllegalOrderTransition { order_id, from, to }
+            }
+            lib_core::error::Error::NotDeadLettered { order_id } => Error::NotDeadLettered(order_id),
+            lib_core::error::Error::BookNotFound(book) => Error::BookNotFound(book),
+            lib_core::error::Error::BookConflict { field, value } => Error::BookConflict { field, value },
+            _ => Error::WebError,
+        }
     }
 }
 
@@ -133,6 +152,26 @@ impl Error {
             }
             // -- Auth
             CtxExt(_) => (StatusCode::FORBIDDEN, ClientError::LOGIN_FAIL),
+            UnauthorizedAccess => (StatusCode::FORBIDDEN, ClientError::NO_AUTH),
+
+            // -- Order
+            NotDeadLettered(order_id) => (
+                StatusCode::NOT_FOUND,
+                ClientError::ENTITY_NOT_FOUND { entity: "failed_order", id: *order_id },
+            ),
+            InvalidQuantity(detail) => (StatusCode::BAD_REQUEST, ClientError::RPC_PARAMS_INVALID(detail.clone())),
+            IllegalOrderTransition { order_id, from, to } => (
+                StatusCode::CONFLICT,
+                ClientError::ILLEGAL_ORDER_TRANSITION { order_id: *order_id, from: from.clone(), to: to.clone() },
+            ),
+
+            // -- Book
+            BookNotFound(book) => (StatusCode::NOT_FOUND, ClientError::BOOK_NOT_FOUND(book.clone())),
+            BookConflict { field, value } => (
+                StatusCode::CONFLICT,
+                ClientError::BOOK_CONFLICT { field, value: value.clone() },
+            ),
+            InvalidBook(detail) => (StatusCode::BAD_REQUEST, ClientError::RPC_PARAMS_INVALID(detail.clone())),
 
 
             //
@@ -192,6 +231,9 @@ pub enum ClientError {
     LOGIN_FAIL,
     NO_AUTH,
     ENTITY_NOT_FOUND { entity: &'static str, id: i64 },
+    ILLEGAL_ORDER_TRANSITION { order_id: i64, from: OrderStatus, to: OrderStatus },
+    BOOK_NOT_FOUND(String),
+    BOOK_CONFLICT { field: &'static str, value: String },
 
     RPC_REQUEST_INVALID(String),
     RPC_REQUEST_METHOD_UNKNOWN(String),
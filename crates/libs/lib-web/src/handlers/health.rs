@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use tracing::instrument;
+
+use lib_core::context::app_context::ModelManager;
+use lib_core::health::report::{
+    check_message_bus, check_migrations, check_postgres, CheckReport, CheckStatus, CHECK_TIMEOUT, HealthReport,
+};
+use lib_core::task::main_task::TaskManager;
+
+/// Liveness of the web-server only depends on its own tasks, a database outage must not restart it.
+#[instrument(skip_all)]
+pub async fn live(
+    State(app_context): State<Arc<ModelManager>>,
+) -> (StatusCode, Json<HealthReport>) {
+    response(HealthReport::new(task_checks(&app_context).await))
+}
+
+/// The web-server is ready once every task answers and all its dependencies are reachable.
+#[instrument(skip_all)]
+pub async fn ready(
+    State(app_context): State<Arc<ModelManager>>,
+) -> (StatusCode, Json<HealthReport>) {
+    let (mut checks, postgres, migrations, message_bus) = tokio::join!(
+        task_checks(&app_context),
+        check_postgres(&app_context),
+        check_migrations(&app_context),
+        check_message_bus(app_context.app_config()),
+    );
+    checks.extend([postgres, migrations, message_bus]);
+
+    response(HealthReport::new(checks))
+}
+
+/// The auth-server runs no tasks, so it is alive as long as it serves requests.
+#[instrument(skip_all)]
+pub async fn auth_live() -> (StatusCode, Json<HealthReport>) {
+    response(HealthReport::new(Vec::new()))
+}
+
+#[instrument(skip_all)]
+pub async fn auth_ready(
+    State(app_context): State<Arc<ModelManager>>,
+) -> (StatusCode, Json<HealthReport>) {
+    let (postgres, migrations) = tokio::join!(
+        check_postgres(&app_context),
+        check_migrations(&app_context),
+    );
+
+    response(HealthReport::new(vec![postgres, migrations]))
+}
+
+async fn task_checks(app_context: &ModelManager) -> Vec<CheckReport> {
+    // every task is checked with CHECK_TIMEOUT concurrently, the extra second covers the main loop itself
+    let timeout = CHECK_TIMEOUT + std::time::Duration::from_secs(1);
+    match tokio::time::timeout(timeout, TaskManager::health(app_context.main_tx())).await {
+        Ok(Ok(checks)) => checks,
+        Ok(Err(e)) => vec![CheckReport::down("TaskManager", e.to_string())],
+        Err(_) => vec![CheckReport::down("TaskManager", format!("timed out after {:?}", timeout))],
+    }
+}
+
+fn response(report: HealthReport) -> (StatusCode, Json<HealthReport>) {
+    let status_code = match report.status() {
+        CheckStatus::Up => StatusCode::OK,
+        CheckStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status_code, Json(report))
+}
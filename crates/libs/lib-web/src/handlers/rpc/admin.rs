@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tracing::warn;
+
+use lib_core::bmc::failed_order::FailedOrderBmc;
+use lib_core::bmc::general;
+use lib_core::bmc::storage::StorageBmc;
+use lib_core::context::app_context::ModelManager;
+use lib_core::task::kafka::dead_letter;
+use lib_core::task::kafka::republish;
+use lib_core::task::main_task::TaskManager;
+use lib_core::task::status::STATUS_TIMEOUT;
+use lib_dto::book::StockList;
+use lib_dto::event::{DeadLetterReplay, OrderRepublish};
+use lib_dto::order::OrderId;
+
+use crate::ctx::Ctx;
+use crate::error::{Error, Result};
+use crate::handlers::rpc::order::ensure_quantities;
+
+/// Admin methods are only open to the phones listed in `AppConfig::admin_phones`.
+pub(super) fn ensure_admin(mm: &ModelManager, ctx: &Ctx) -> Result<()> {
+    if mm.app_config().admin_phones.iter().any(|phone| phone == ctx.phone()) {
+        return Ok(());
+    }
+    warn!("Admin method called by non admin {}", ctx.phone());
+    Err(Error::UnauthorizedAccess)
+}
+
+pub(super) async fn admin_tasks(mm: &ModelManager, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    // every task is asked with STATUS_TIMEOUT concurrently, the extra second covers the main loop itself
+    let timeout = STATUS_TIMEOUT + Duration::from_secs(1);
+    let statuses = tokio::time::timeout(timeout, TaskManager::statuses(mm.main_tx())).await
+        .map_err(|_| Error::Anyhow)??;
+    Ok(json!(statuses))
+}
+
+/// Adds stock, orders can only reserve what was restocked.
+pub(super) async fn restock_books(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let stock_list: StockList = serde_json::from_value(params)?;
+    ensure_quantities(stock_list.stock())?;
+    StorageBmc::restock(mm, stock_list.stock()).await?;
+    Ok(Value::Null)
+}
+
+/// Dead-lettered orders which were not requeued yet.
+pub(super) async fn failed_orders(mm: &ModelManager, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    Ok(json!(FailedOrderBmc::list_pending(mm).await?))
+}
+
+/// Sends a dead-lettered order through the pipeline again.
+pub(super) async fn requeue_order(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let order_id: OrderId = serde_json::from_value(params)?;
+    let actor = format!("admin:{}", ctx.phone());
+    let order_stored = general::requeue_order(mm, order_id.order_id(), &actor).await?;
+    TaskManager::submit_order(mm.main_tx(), order_stored.clone()).await?;
+    Ok(json!(order_stored))
+}
+
+/// Moves dead-lettered order events back to the order topic, answers how many were moved.
+pub(super) async fn replay_dead_letters(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let replay: DeadLetterReplay = serde_json::from_value(params)?;
+    let app_config = mm.app_config();
+    let replayed = dead_letter::replay(
+        mm.message_bus().as_ref(),
+        &app_config.dead_letter.topic,
+        &app_config.kafka_producer.topic,
+        replay.limit(),
+    ).await?;
+    Ok(json!(replayed))
+}
+
+/// Publishes the events of the selected orders again, answers how many matched and were republished.
+pub(super) async fn republish_orders(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let request: OrderRepublish = serde_json::from_value(params)?;
+    Ok(json!(republish::republish_orders(mm, &request).await?))
+}
@@ -1,22 +1,23 @@
 use serde_json::{json, Value};
-use tracing::info;
 
 use lib_core::bmc::book_info::BookBmc;
 use lib_core::context::app_context::ModelManager;
-use lib_dto::book::{BookDescription, BookList};
+use lib_dto::book::{BookDescription, BookInfo, BookKey, BookList};
 
-use crate::error::Result;
+use crate::ctx::Ctx;
+use crate::error::{Error, Result};
+use crate::handlers::rpc::admin::ensure_admin;
 
-pub(super) async fn add_books(mm: &ModelManager, params: Value) -> Result<Value> {
-    let books = BookBmc::get_all(mm).await?;
-    if books.book_list().len() == 5 {
-        info!("All books are already inserted.");
-        return Ok(Value::Null);
-    }
+/// Lengths of the `book_info` columns, in characters.
+const MAX_TITLE_LEN: usize = 128;
+const MAX_AUTHOR_LEN: usize = 256;
+const MAX_ISBN_LEN: usize = 128;
 
+/// Adds the books whose title and isbn are not taken yet, the others are left as they are.
+pub(super) async fn add_books(mm: &ModelManager, params: Value) -> Result<Value> {
     let book_list: BookList = serde_json::from_value(params)?;
     for book_info in book_list.book_list().iter() {
-        BookBmc::create(mm, book_info).await?;
+        BookBmc::create_missing(mm, book_info).await?;
     }
     Ok(Value::Null)
 }
@@ -28,4 +29,77 @@ pub(super) async fn all_books(mm: &ModelManager) -> Result<Value> {
 pub(super) async fn books_by_description(mm: &ModelManager, params: Value) -> Result<Value> {
     let description: BookDescription = serde_json::from_value(params)?;
     Ok(json!(BookBmc::get_by_description(mm, description.description()).await?))
-}
\ No newline at end of file
+}
+
+/// A book of the catalog by id or isbn, deleted books are not found.
+pub(super) async fn get_book(mm: &ModelManager, params: Value) -> Result<Value> {
+    let key: BookKey = serde_json::from_value(params)?;
+    Ok(json!(BookBmc::get(mm, &key).await?))
+}
+
+/// Answers the created book with its id.
+pub(super) async fn create_book(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let book: BookInfo = serde_json::from_value(params)?;
+    ensure_valid(&book)?;
+    let id = BookBmc::create(mm, &book).await?;
+    Ok(json!(BookBmc::get(mm, &BookKey::BookId(id)).await?))
+}
+
+/// Replaces every field of the book with the given id.
+pub(super) async fn update_book(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let book: BookInfo = serde_json::from_value(params)?;
+    let id = book.id.ok_or_else(|| Error::InvalidBook("id is required".to_string()))?;
+    ensure_valid(&book)?;
+    Ok(json!(BookBmc::update(mm, id, &book).await?))
+}
+
+/// Removes the book from the catalog, its title and isbn stay taken.
+pub(super) async fn delete_book(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let key: BookKey = serde_json::from_value(params)?;
+    let book = BookBmc::get(mm, &key).await?;
+    if let Some(id) = book.id {
+        BookBmc::delete(mm, id).await?;
+    }
+    Ok(Value::Null)
+}
+
+/// Creates or updates the books by isbn, a deleted book is restored. Nothing is imported if one fails.
+pub(super) async fn import_books(mm: &ModelManager, params: Value, ctx: Ctx) -> Result<Value> {
+    ensure_admin(mm, &ctx)?;
+    let book_list: BookList = serde_json::from_value(params)?;
+    if book_list.book_list().is_empty() {
+        return Err(Error::InvalidBook("No books".to_string()));
+    }
+    for book in book_list.book_list() {
+        ensure_valid(book)?;
+    }
+    Ok(json!(BookBmc::import(mm, book_list.book_list()).await?))
+}
+
+/// Title and isbn are required, every field must fit its column.
+fn ensure_valid(book: &BookInfo) -> Result<()> {
+    ensure_text("title", &book.title, MAX_TITLE_LEN)?;
+    ensure_text("isbn", &book.isbn, MAX_ISBN_LEN)?;
+    if let Some(author) = &book.author {
+        ensure_length("author", author, MAX_AUTHOR_LEN)?;
+    }
+    Ok(())
+}
+
+fn ensure_text(field: &str, value: &str, max_len: usize) -> Result<()> {
+    if value.trim().is_empty() {
+        return Err(Error::InvalidBook(format!("{} is required", field)));
+    }
+    ensure_length(field, value, max_len)
+}
+
+fn ensure_length(field: &str, value: &str, max_len: usize) -> Result<()> {
+    if value.chars().count() > max_len {
+        return Err(Error::InvalidBook(format!("{} must be at most {} characters", field, max_len)));
+    }
+    Ok(())
+}
+
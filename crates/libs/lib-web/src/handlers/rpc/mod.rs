@@ -13,10 +13,12 @@ use lib_core::context::app_context::ModelManager;
 use order::create_order;
 
 use crate::ctx::{Ctx, CtxW};
+use crate::handlers::rpc::admin::{admin_tasks, failed_orders, replay_dead_letters, republish_orders, requeue_order, restock_books};
 use crate::error::Error::{RpcNoParams, RpcRequestParsing, UnknownRpcMethod};
 use crate::error::Result;
-use crate::handlers::rpc::order::{check_order, clean_up, pick_up_order};
+use crate::handlers::rpc::order::{cancel_order, check_order, clean_up, order_history, pick_up_order, watch_order};
 
+pub mod admin;
 pub mod book;
 pub mod order;
 
@@ -85,7 +87,7 @@ pub async fn rpc(
     // Note: We store data in the Axum Response extensions so that
     //       we can unpack it in the `mw_res_map` for client-side rendering.
     //       This approach centralizes error handling for the client at the `mw_res_map` module
-    let res: crate::error::Result<_> = res.map_err(crate::error::Error::from);
+    let res: crate::error::Result<_> = res;
     let mut res = res.into_response();
     // Note: Here, add the capture RpcInfo (RPC ID and method) into the Axum response to be used
     //       later in the `mw_res_map` for RequestLineLogging, and eventual JSON-RPC error serialization.
@@ -100,9 +102,23 @@ async fn call_rpc(app_context: &ModelManager, ctx: Ctx, rpc_req: Request) -> Res
         "add_books" => add_books(app_context, params(rpc_req)?).await,
         "all_books" => all_books(app_context).await,
         "books_by_description" => books_by_description(app_context, params(rpc_req)?).await,
+        "get_book" => get_book(app_context, params(rpc_req)?).await,
+        "create_book" => create_book(app_context, params(rpc_req)?, ctx).await,
+        "update_book" => update_book(app_context, params(rpc_req)?, ctx).await,
+        "delete_book" => delete_book(app_context, params(rpc_req)?, ctx).await,
+        "import_books" => import_books(app_context, params(rpc_req)?, ctx).await,
         "create_order" => create_order(app_context, params(rpc_req)?, ctx).await,
         "check_order" => check_order(app_context, params(rpc_req)?, ctx).await,
         "pick_up_order" => pick_up_order(app_context, params(rpc_req)?, ctx).await,
+        "cancel_order" => cancel_order(app_context, params(rpc_req)?, ctx).await,
+        "order_history" => order_history(app_context, params(rpc_req)?, ctx).await,
+        "watch_order" => watch_order(app_context, params(rpc_req)?, ctx).await,
+        "admin_tasks" => admin_tasks(app_context, ctx).await,
+        "restock_books" => restock_books(app_context, params(rpc_req)?, ctx).await,
+        "failed_orders" => failed_orders(app_context, ctx).await,
+        "requeue_order" => requeue_order(app_context, params(rpc_req)?, ctx).await,
+        "replay_dead_letters" => replay_dead_letters(app_context, params(rpc_req)?, ctx).await,
+        "republish_orders" => republish_orders(app_context, params(rpc_req)?, ctx).await,
         method => Err(UnknownRpcMethod(method.to_string())),
     }
 }
@@ -1,16 +1,25 @@
+use std::time::Duration;
+
 use serde_json::{json, Value};
-use tracing::error;
+use tokio::sync::broadcast::error::RecvError;
 
+use lib_core::bmc::book_sales::BookSalesBmc;
+use lib_core::bmc::general;
 use lib_core::bmc::order::OrderBmc;
 use lib_core::bmc::storage::StorageBmc;
 use lib_core::bmc::user::UserBmc;
 use lib_core::context::app_context::ModelManager;
-use lib_dto::order::{OrderContent, OrderForCreate, OrderId, OrderStatus};
+use lib_dto::order::{OrderContent, OrderForCreate, OrderId, OrderItem, OrderStored, OrderWatch};
+use lib_dto::user::UserStored;
 
 use crate::ctx::Ctx;
 
+/// How long `watch_order` waits for a change before it answers with the unchanged order.
+const WATCH_ORDER_TIMEOUT: Duration = Duration::from_secs(25);
+
 pub(super) async fn create_order(mm: &ModelManager, params: Value, ctx: Ctx) -> crate::error::Result<Value> {
     let order_content: OrderContent = serde_json::from_value(params)?;
+    ensure_quantities(order_content.content())?;
     let user_stored = UserBmc::get_by_phone(mm, ctx.phone()).await?;
     let order_for_create = OrderForCreate::new(user_stored.id(), order_content);
     let order_id = OrderBmc::create(mm, order_for_create).await.unwrap();
@@ -19,36 +28,93 @@ pub(super) async fn create_order(mm: &ModelManager, params: Value, ctx: Ctx) ->
 }
 
 pub(super) async fn check_order(mm: &ModelManager, params: Value, ctx: Ctx) -> crate::error::Result<Value> {
-    //todo return 404 when order id does not exist
     let order_id: OrderId = serde_json::from_value(params)?;
-    let order_stored = OrderBmc::get_by_id(mm, order_id.order_id()).await?;
-    let user_stored = UserBmc::get_by_id(mm, order_stored.user_id()).await?;
-    if !ctx.phone().eq(user_stored.phone()) {
-        return Err(crate::error::Error::UnauthorizedAccess)
-    }
+    let (order_stored, _) = owned_order(mm, order_id.order_id(), &ctx).await?;
 
     Ok(json!(order_stored))
 }
 
 pub(super) async fn pick_up_order(mm: &ModelManager, params: Value, ctx: Ctx) -> crate::error::Result<Value> {
-    //todo return 404 when order id does not exist
     let order_id: OrderId = serde_json::from_value(params)?;
-    let order_stored = OrderBmc::get_by_id(mm, order_id.order_id()).await?;
+    let (_, user_stored) = owned_order(mm, order_id.order_id(), &ctx).await?;
+    let actor = format!("user:{}", user_stored.id());
+    let order_stored = general::pick_up_order(mm, order_id.order_id(), &actor).await?;
+
+    Ok(json!(order_stored))
+}
+
+pub(super) async fn cancel_order(mm: &ModelManager, params: Value, ctx: Ctx) -> crate::error::Result<Value> {
+    let order_id: OrderId = serde_json::from_value(params)?;
+    let (_, user_stored) = owned_order(mm, order_id.order_id(), &ctx).await?;
+    let actor = format!("user:{}", user_stored.id());
+    let order_stored = general::cancel_order(mm, order_id.order_id(), &actor, Some("cancelled by customer")).await?;
+
+    Ok(json!(order_stored))
+}
+
+pub(super) async fn order_history(mm: &ModelManager, params: Value, ctx: Ctx) -> crate::error::Result<Value> {
+    let order_id: OrderId = serde_json::from_value(params)?;
+    owned_order(mm, order_id.order_id(), &ctx).await?;
+    let history = OrderBmc::history(mm, order_id.order_id()).await?;
+
+    Ok(json!(history))
+}
+
+/// Answers once the order left the status the caller knows, or with the order as it is after
+/// `WATCH_ORDER_TIMEOUT`, so a client follows its order by calling again with the status it got.
+pub(super) async fn watch_order(mm: &ModelManager, params: Value, ctx: Ctx) -> crate::error::Result<Value> {
+    let order_watch: OrderWatch = serde_json::from_value(params)?;
+    // subscribed before loading, so a change in between is not missed
+    let mut updates = mm.subscribe_order_updates();
+    let (mut order_stored, _) = owned_order(mm, order_watch.order_id(), &ctx).await?;
+
+    let watched = tokio::time::timeout(WATCH_ORDER_TIMEOUT, async {
+        while order_stored.status() == order_watch.status() {
+            match updates.recv().await {
+                Ok(order) if order.order_id() == order_watch.order_id() => order_stored = order,
+                Ok(_) => {}
+                // missed updates may include this order's
+                Err(RecvError::Lagged(_)) => order_stored = OrderBmc::get_by_id(mm, order_watch.order_id()).await?,
+                Err(RecvError::Closed) => break,
+            }
+        }
+        Ok::<_, crate::error::Error>(())
+    }).await;
+    if let Ok(watched) = watched {
+        watched?;
+    }
+
+    Ok(json!(order_stored))
+}
+
+/// Every item must ask for at least one book, a negative quantity would add to the stock.
+pub(super) fn ensure_quantities(items: &[OrderItem]) -> crate::error::Result<()> {
+    if items.is_empty() {
+        return Err(crate::error::Error::InvalidQuantity("No items".to_string()));
+    }
+    match items.iter().find(|item| item.quantity() <= 0) {
+        Some(item) => Err(crate::error::Error::InvalidQuantity(
+            format!("Quantity of book {} must be positive", item.book_id())
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Loads the order and its owner, only the owner may see or touch it.
+async fn owned_order(mm: &ModelManager, order_id: i64, ctx: &Ctx) -> crate::error::Result<(OrderStored, UserStored)> {
+    //todo return 404 when order id does not exist
+    let order_stored = OrderBmc::get_by_id(mm, order_id).await?;
     let user_stored = UserBmc::get_by_id(mm, order_stored.user_id()).await?;
     if !ctx.phone().eq(user_stored.phone()) {
         return Err(crate::error::Error::UnauthorizedAccess)
     }
-    match OrderBmc::update_status(mm, order_id.order_id(), OrderStatus::Delivered).await {
-        Ok(_) => {}
-        Err(e) => {
-            error!("Failed to update status for order {}, error: {:#?}", order_id.order_id(), e)
-        }
-    }
-    Ok(json!(order_stored))
+
+    Ok((order_stored, user_stored))
 }
 
 pub(super) async fn clean_up(mm: &ModelManager) -> crate::error::Result<Value> {
     OrderBmc::cleanup_orders(mm).await?;
     StorageBmc::cleanup_storage(mm).await?;
+    BookSalesBmc::cleanup_sales(mm).await?;
     Ok(json!("Ignored"))
 }
\ No newline at end of file
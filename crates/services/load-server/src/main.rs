@@ -4,7 +4,10 @@ use std::time::Duration;
 use tokio::time::sleep;
 use tracing::info;
 
+use lib_dto::book::StockList;
+use lib_dto::order::OrderItem;
 use lib_load::requests::user_context::UserContext;
+use lib_load::scenario::common::BOOKS_SIZE;
 use lib_load::scenario::load::start_user;
 use lib_load::scenario::stage_01::load;
 
@@ -14,6 +17,8 @@ use lib_load::scenario::stage_01::load;
 // use crate::utils::file::from_file;
 
 const USERS_NUM: usize = 1;
+/// Per book, far more than the scenario orders.
+const STOCK: i64 = 1_000_000;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -28,7 +33,10 @@ async fn main() -> Result<(), Box<dyn Error>> {
     for i in 1..=USERS_NUM {
         users.push(start_user(i).await);
     }
-    users.get_mut(0).expect("must be some").clean_up().await;
+    let first_user = users.get_mut(0).expect("must be some");
+    first_user.clean_up().await;
+    let stock = (1..=BOOKS_SIZE as i64).map(|book_id| OrderItem::new(book_id, STOCK)).collect();
+    first_user.restock(StockList::new(stock)).await;
     sleep(Duration::from_secs(1)).await;
     load(users).await;
 
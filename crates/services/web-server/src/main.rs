@@ -1,21 +1,21 @@
 use std::error::Error;
 use std::sync::Arc;
 
-use console_subscriber;
 use dotenv::dotenv;
 use opentelemetry::{global, KeyValue, runtime};
 use opentelemetry::sdk::{Resource, trace as sdktrace, trace};
 use opentelemetry::sdk::propagation::TraceContextPropagator;
-use opentelemetry::trace::{FutureExt, TraceError};
+use opentelemetry::trace::TraceError;
 use opentelemetry_otlp::WithExportConfig;
-use tokio::select;
+use tokio::{select, signal};
 use tokio_util::sync::CancellationToken;
-use tracing::{info, Level};
+use tracing::{error, info, Level};
 use tracing_subscriber::{EnvFilter, filter, fmt};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::prelude::*;
 
 use lib_core::context::app_context::ModelManager;
+use lib_core::task::main_task::TaskManager;
 use lib_web::app::context::create_app_context;
 use lib_web::app::web_app::web_app;
 
@@ -28,7 +28,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     //     .init();
 
     // log level filtering here
-    let filter_layer = EnvFilter::try_from_default_env()
+    let _filter_layer = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new("info"))
         .unwrap();
 
@@ -56,17 +56,44 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await.unwrap();
     let cancellation_token: CancellationToken = app_context.cancellation_token();
 
-    select! {
-        _ = lib_core::task::main_task::TaskManager::start(rx, app_context) => {}
-        _ = axum::serve(listener, app) => {}
-        _ = cancellation_token.cancelled() => {
-                info!("Cancelled by cancellation token.")
-            }
-    };
+    let task_manager = tokio::spawn(TaskManager::start(rx, app_context.clone()));
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal(cancellation_token))
+        .await?;
+
+    info!("HTTP server stopped, draining tasks");
+    if let Err(e) = TaskManager::shutdown(&app_context).await {
+        error!("Failed to drain tasks: {:#?}", e);
+    }
+    let _ = task_manager.await;
 
     Ok(())
 }
 
+async fn shutdown_signal(cancellation_token: CancellationToken) {
+    let ctrl_c = async {
+        signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+        _ = cancellation_token.cancelled() => info!("Cancelled by cancellation token."),
+    }
+}
+
 fn init_trace() -> Result<sdktrace::Tracer, TraceError> {
     // Configure the Jaeger exporter
     opentelemetry_otlp::new_pipeline()
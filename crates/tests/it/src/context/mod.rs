@@ -1 +1,2 @@
+#[allow(clippy::module_inception)]
 pub(super) mod context;
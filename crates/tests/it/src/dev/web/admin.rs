@@ -0,0 +1,197 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+    use serial_test::serial;
+
+    use lib_core::bmc::storage::StorageBmc;
+    use lib_core::bus::{MessageBusConfig, DEFAULT_PARTITIONS};
+    use lib_dto::book::{BookList, StockList};
+    use lib_dto::event::{OrderEvent, OrderRepublish, OrderRepublishReport};
+    use lib_dto::order::{OrderContent, OrderId, OrderItem, OrderStatus, OrderStored};
+    use lib_load::requests::user_context::UserContext;
+    use lib_load::scenario::books::BOOK_LIST;
+    use lib_utils::rpc::request;
+
+    use crate::context::context::{ServiceType, TestContext};
+    use crate::dev::web::{login, restock};
+
+    #[tokio::test]
+    #[serial]
+    async fn admin_tasks() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut admin = ctx.user(0);
+        login(&mut ctx, &mut admin).await;
+
+        let statuses: Value = admin.post_rpc("admin_tasks", Value::Null).await;
+        let statuses = statuses.as_array().expect("must be array");
+        let names: Vec<&str> = statuses.iter()
+            .map(|status| status["name"].as_str().expect("must be str"))
+            .collect();
+        assert_eq!(
+            vec!["NotifyTask", "RecoveryTask", "KafkaConsumerTask", "OrderTask", "StorageTask", "DeliveryTask", "KafkaProducerTask"],
+            names,
+        );
+        for status in statuses {
+            assert_eq!(status["restarts"], 0);
+            assert_eq!(status["queue_capacity"], 64);
+            assert!(status["in_flight_orders"].is_array());
+        }
+        assert_eq!(statuses[3]["worker_pool"]["workers"], 8);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn admin_tasks_forbidden() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let response = user.post("/api/rpc", request("admin_tasks", Some(Value::Null))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn restock_books() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut admin = ctx.user(0);
+        login(&mut ctx, &mut admin).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = admin.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+
+        let stock_list = StockList::new(vec!(OrderItem::new(1, 5), OrderItem::new(2, 3)));
+        let _: Value = admin.post_rpc("restock_books", json!(stock_list)).await;
+        let _: Value = admin.post_rpc("restock_books", json!(stock_list)).await;
+        let storage = StorageBmc::get_quantity(ctx.app_context(), 1).await.expect("must be ok");
+        assert_eq!(Some(10), storage.quantity());
+
+        let response = user.post("/api/rpc", request("restock_books", Some(json!(stock_list)))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let stock_list = StockList::new(vec!(OrderItem::new(1, -5)));
+        let response = admin.post("/api/rpc", request("restock_books", Some(json!(stock_list)))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn requeue_order() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut admin = ctx.user(0);
+        login(&mut ctx, &mut admin).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = admin.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+
+        // nothing is in stock, so the order fails without being dead-lettered
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Failed, wait_status(&user, &order_id, OrderStatus::Failed).await);
+        let failed_orders: Value = admin.post_rpc("failed_orders", Value::Null).await;
+        assert_eq!(json!([]), failed_orders);
+        let response = admin.post("/api/rpc", request("requeue_order", Some(json!(order_id)))).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // as if the storage update had exhausted its retries
+        sqlx::query("INSERT INTO failed_order (order_id, stage, attempts, last_error) VALUES ($1, 'StorageTask', 5, 'deadlock')")
+            .bind(order_id.order_id())
+            .execute(ctx.app_context().pg_pool())
+            .await
+            .expect("must be ok");
+        let failed_orders: Value = admin.post_rpc("failed_orders", Value::Null).await;
+        assert_eq!(failed_orders[0]["order_id"], order_id.order_id());
+
+        let response = user.post("/api/rpc", request("requeue_order", Some(json!(order_id)))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        restock(&ctx, 10).await;
+        let _: Value = admin.post_rpc("requeue_order", json!(order_id)).await;
+        assert_eq!(OrderStatus::Delivered, wait_status(&user, &order_id, OrderStatus::Delivered).await);
+
+        let failed_orders: Value = admin.post_rpc("failed_orders", Value::Null).await;
+        assert_eq!(json!([]), failed_orders);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn republish_orders() {
+        let mut ctx = TestContext::with_message_bus(ServiceType::Web, MessageBusConfig::InMemory { partitions: DEFAULT_PARTITIONS }).await;
+        let mut admin = ctx.user(0);
+        login(&mut ctx, &mut admin).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = admin.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Delivered, wait_status(&user, &order_id, OrderStatus::Delivered).await);
+
+        let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+        // the bounds are inclusive
+        let created_at = Some(order.created_at());
+        let request_for = |status: OrderStatus| OrderRepublish::new().with_status(status).with_created(created_at, created_at);
+        let dry_run: OrderRepublishReport = admin.post_rpc("republish_orders", json!(request_for(OrderStatus::Delivered).with_dry_run(true))).await;
+        assert_eq!(OrderRepublishReport::new(1, 0, true), dry_run);
+        let nothing: OrderRepublishReport = admin.post_rpc("republish_orders", json!(request_for(OrderStatus::Cancelled))).await;
+        assert_eq!(OrderRepublishReport::new(0, 0, false), nothing);
+
+        let response = user.post("/api/rpc", request("republish_orders", Some(json!(request_for(OrderStatus::Delivered))))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let republished: OrderRepublishReport = admin.post_rpc("republish_orders", json!(request_for(OrderStatus::Delivered).with_rate_limit(5))).await;
+        assert_eq!(OrderRepublishReport::new(1, 1, false), republished);
+
+        // the event of the delivery and the republished one
+        let app_config = ctx.app_context().app_config();
+        let consumer = ctx.app_context().message_bus()
+            .subscribe(&[app_config.kafka_producer.topic.as_str()], "republish-test").await
+            .expect("must be ok");
+        let mut delivered = 0;
+        while delivered < 2 {
+            let message = tokio::time::timeout(Duration::from_secs(5), consumer.recv()).await
+                .expect("must be republished")
+                .expect("must be ok");
+            let event: OrderEvent = serde_json::from_str(message.payload_str().expect("must be ok")).expect("must be ok");
+            if event.order_id() == order_id.order_id() && event.payload().status() == &OrderStatus::Delivered {
+                delivered += 1;
+            }
+        }
+
+        ctx.cancel().await;
+    }
+
+    async fn wait_status(user: &UserContext, order_id: &OrderId, status: OrderStatus) -> OrderStatus {
+        let reached = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+                if order.status() == &status {
+                    return order.status().clone();
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await;
+        reached.expect("must reach the status")
+    }
+}
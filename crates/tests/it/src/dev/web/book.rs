@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+    use serial_test::serial;
+
+    use lib_dto::book::{BookImportReport, BookInfo, BookKey, BookList};
+    use lib_load::scenario::books::BOOK_LIST;
+    use lib_load::utils::body_utils::message_from_response;
+    use lib_utils::rpc::request;
+
+    use crate::context::context::{ServiceType, TestContext};
+    use crate::dev::web::login;
+
+    fn book(title: &str, isbn: &str) -> BookInfo {
+        BookInfo::new(title.to_string(), Some("Author".to_string()), isbn.to_string(), "description".to_string())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn book_catalog() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut admin = ctx.user(0);
+        login(&mut ctx, &mut admin).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let created: BookInfo = admin.post_rpc("create_book", json!(book("Hyperion", "isbn-1"))).await;
+        let id = created.id.expect("must have an id");
+        let by_isbn: BookInfo = user.post_rpc("get_book", json!(BookKey::Isbn("isbn-1".to_string()))).await;
+        assert_eq!(Some(id), by_isbn.id);
+        assert_eq!("Hyperion", by_isbn.title);
+
+        let updated: BookInfo = admin.post_rpc("update_book", json!(book("Hyperion Cantos", "isbn-1").with_id(id))).await;
+        assert_eq!("Hyperion Cantos", updated.title);
+
+        let response = user.post("/api/rpc", request("create_book", Some(json!(book("Ilium", "isbn-2"))))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let (message, _) = admin.post_bad("create_book", json!(book(" ", "isbn-2"))).await;
+        assert_eq!("RPC_PARAMS_INVALID", message);
+        let (message, _) = admin.post_bad("update_book", json!(book("Ilium", "isbn-2"))).await;
+        assert_eq!("RPC_PARAMS_INVALID", message);
+
+        let _: Value = admin.post_rpc("delete_book", json!(BookKey::BookId(id))).await;
+        let response = user.post("/api/rpc", request("get_book", Some(json!(BookKey::BookId(id))))).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!("BOOK_NOT_FOUND", message_from_response(response).await);
+        let all_books: BookList = user.post_rpc("all_books", Value::Null).await;
+        assert!(all_books.book_list().is_empty());
+
+        // a deleted book keeps its isbn, importing it restores the book
+        let response = admin.post("/api/rpc", request("create_book", Some(json!(book("Ilium", "isbn-1"))))).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let import = BookList::new(vec![book("Hyperion", "isbn-1"), book("Ilium", "isbn-2")]);
+        let report: BookImportReport = admin.post_rpc("import_books", json!(import)).await;
+        assert_eq!(BookImportReport::new(1, 1), report);
+        let restored: BookInfo = user.post_rpc("get_book", json!(BookKey::BookId(id))).await;
+        assert_eq!("Hyperion", restored.title);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn book_conflicts() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut admin = ctx.user(0);
+        login(&mut ctx, &mut admin).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let response = admin.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // adding them again leaves them as they are
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let response = admin.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let all_books: BookList = admin.post_rpc("all_books", Value::Null).await;
+        assert_eq!(5, all_books.book_list().len());
+        assert!(all_books.book_list().iter().all(|book| book.id.is_some()));
+
+        let response = admin.post("/api/rpc", request("create_book", Some(json!(book("Dune", "isbn-new"))))).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!("BOOK_CONFLICT", message_from_response(response).await);
+
+        // the title of Dune under a new isbn, nothing of the import is kept
+        let import = BookList::new(vec![book("Ilium", "isbn-2"), book("Dune", "isbn-3")]);
+        let response = admin.post("/api/rpc", request("import_books", Some(json!(import)))).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = admin.post("/api/rpc", request("get_book", Some(json!(BookKey::Isbn("isbn-2".to_string()))))).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        ctx.cancel().await;
+    }
+}
@@ -0,0 +1,171 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use anyhow::{bail, Result};
+    use serde_json::json;
+    use serial_test::serial;
+    use sqlx::{Postgres, Transaction};
+
+    use lib_core::bmc::consumer_offset::ConsumerOffsetBmc;
+    use lib_core::bus::{BusRecord, MessageBusConfig, StartOffsets, DEFAULT_PARTITIONS};
+    use lib_core::task::actor::BoxFuture;
+    use lib_core::task::kafka::consumer_task::StoredOffsets;
+    use lib_core::task::kafka::dead_letter::{DLQ_ERROR, DLQ_SOURCE_OFFSET, DLQ_SOURCE_TOPIC};
+    use lib_core::task::kafka::dispatcher::{Dispatcher, OrderEventHandler};
+    use lib_dto::event::{DeadLetterReplay, OrderEvent};
+
+    use crate::context::context::{ServiceType, TestContext};
+    use crate::dev::web::login;
+
+    const GROUP: &str = "test-group";
+    const TOPIC: &str = "test-topic";
+
+    struct Recorder {
+        name: &'static str,
+        seen: Arc<Mutex<Vec<(&'static str, i64)>>>,
+        fail: bool,
+    }
+
+    impl OrderEventHandler for Recorder {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn handle<'a>(&'a self, _tx: &'a mut Transaction<'static, Postgres>, event: &'a OrderEvent) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                self.seen.lock().unwrap().push((self.name, event.order_id()));
+                if self.fail {
+                    bail!("broken");
+                }
+                Ok(())
+            })
+        }
+    }
+
+    fn event(order_id: i64) -> OrderEvent {
+        let payload = format!(r#"{{"order_id": {order_id}, "user_id": 1, "content": {{"content": []}},
+            "status": "delivered", "created_at": "2024-05-01T10:00:00Z", "updated_at": "2024-05-01T10:00:01Z"}}"#);
+        OrderEvent::new(serde_json::from_str(&payload).expect("must be ok"))
+    }
+
+    async fn in_memory_context() -> TestContext {
+        TestContext::with_message_bus(ServiceType::Web, MessageBusConfig::InMemory { partitions: DEFAULT_PARTITIONS }).await
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn dispatch_in_order_until_failure() {
+        let ctx = in_memory_context().await;
+        let seen = Arc::new(Mutex::new(vec![]));
+        let dispatcher = Dispatcher::default()
+            .register(Recorder { name: "first", seen: seen.clone(), fail: false })
+            .register(Recorder { name: "second", seen: seen.clone(), fail: true })
+            .register(Recorder { name: "third", seen: seen.clone(), fail: false });
+
+        let mut tx = ctx.app_context().pg_pool().begin().await.expect("must be ok");
+        let error = dispatcher.dispatch(&mut tx, &event(7)).await.expect_err("must fail");
+        assert_eq!("second failed on order 7", error.to_string());
+        assert_eq!(vec![("first", 7), ("second", 7)], *seen.lock().unwrap());
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn offset_advances_once() {
+        let ctx = in_memory_context().await;
+        let mm = ctx.app_context();
+        assert_eq!(None, ConsumerOffsetBmc::next_offset(mm, GROUP, TOPIC, 0).await.expect("must be ok"));
+
+        let mut tx = mm.pg_pool().begin().await.expect("must be ok");
+        assert!(ConsumerOffsetBmc::advance_tx(&mut tx, GROUP, TOPIC, 0, 5).await.expect("must be ok"));
+        tx.commit().await.expect("must be ok");
+
+        // redelivered, or older than what was processed
+        for offset in [5, 4] {
+            let mut tx = mm.pg_pool().begin().await.expect("must be ok");
+            assert!(!ConsumerOffsetBmc::advance_tx(&mut tx, GROUP, TOPIC, 0, offset).await.expect("must be ok"));
+        }
+
+        // a rolled back advance leaves the offset as it was
+        let mut tx = mm.pg_pool().begin().await.expect("must be ok");
+        assert!(ConsumerOffsetBmc::advance_tx(&mut tx, GROUP, TOPIC, 0, 6).await.expect("must be ok"));
+        tx.rollback().await.expect("must be ok");
+        assert_eq!(Some(6), ConsumerOffsetBmc::next_offset(mm, GROUP, TOPIC, 0).await.expect("must be ok"));
+
+        // compacted or skipped offsets leave gaps
+        let mut tx = mm.pg_pool().begin().await.expect("must be ok");
+        assert!(ConsumerOffsetBmc::advance_tx(&mut tx, GROUP, TOPIC, 0, 9).await.expect("must be ok"));
+        tx.commit().await.expect("must be ok");
+        assert_eq!(Some(10), ConsumerOffsetBmc::next_offset(mm, GROUP, TOPIC, 0).await.expect("must be ok"));
+        // other partitions are tracked apart
+        assert_eq!(None, ConsumerOffsetBmc::next_offset(mm, GROUP, TOPIC, 1).await.expect("must be ok"));
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn reassigned_partition_starts_after_stored_offset() {
+        let ctx = in_memory_context().await;
+        let bus = ctx.app_context().message_bus().clone();
+        let mut published = vec![];
+        for _ in 0..3 {
+            published.push(bus.publish(&BusRecord::new(TOPIC, "1", "event")).await.expect("must be ok"));
+        }
+        let stored: Arc<dyn StartOffsets> = Arc::new(StoredOffsets::new(ctx.app_context().clone(), GROUP));
+
+        // processed by the first member, which leaves the group before committing to the bus
+        let first = bus.subscribe_from(&[TOPIC], GROUP, stored.clone()).await.expect("must be ok");
+        for _ in 0..2 {
+            let message = first.recv().await.expect("must be ok");
+            let mut tx = ctx.app_context().pg_pool().begin().await.expect("must be ok");
+            assert!(ConsumerOffsetBmc::advance_tx(&mut tx, GROUP, TOPIC, message.partition(), message.offset()).await.expect("must be ok"));
+            tx.commit().await.expect("must be ok");
+        }
+        first.unsubscribe();
+
+        let second = bus.subscribe_from(&[TOPIC], GROUP, stored).await.expect("must be ok");
+        let message = tokio::time::timeout(Duration::from_secs(5), second.recv()).await
+            .expect("must be received")
+            .expect("must be ok");
+        assert_eq!(published[2], (message.partition(), message.offset()));
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn malformed_event_dead_lettered_and_replayed() {
+        let mut ctx = in_memory_context().await;
+        let mut admin = ctx.user(0);
+        login(&mut ctx, &mut admin).await;
+        let app_config = ctx.app_context().app_config().clone();
+        let bus = ctx.app_context().message_bus().clone();
+
+        let record = BusRecord::new(app_config.kafka_producer.topic.as_str(), "7", "not an event");
+        let (_, offset) = bus.publish(&record).await.expect("must be ok");
+
+        let dead_letters = bus.subscribe(&[app_config.dead_letter.topic.as_str()], "inspect").await.expect("must be ok");
+        let dead = tokio::time::timeout(Duration::from_secs(5), dead_letters.recv()).await
+            .expect("must be dead-lettered")
+            .expect("must be ok");
+        assert_eq!(Some("7"), dead.key());
+        assert_eq!(Some(app_config.kafka_producer.topic.as_str()), dead.header(DLQ_SOURCE_TOPIC));
+        assert_eq!(Some(offset.to_string().as_str()), dead.header(DLQ_SOURCE_OFFSET));
+        assert!(dead.header(DLQ_ERROR).is_some());
+
+        // still malformed, so it comes back to the dead-letter topic
+        let replayed: usize = admin.post_rpc("replay_dead_letters", json!(DeadLetterReplay::new(1))).await;
+        assert_eq!(1, replayed);
+        let again = tokio::time::timeout(Duration::from_secs(5), dead_letters.recv()).await
+            .expect("must be dead-lettered")
+            .expect("must be ok");
+        assert_eq!(Some("7"), again.key());
+        assert_ne!(dead.header(DLQ_SOURCE_OFFSET), again.header(DLQ_SOURCE_OFFSET));
+
+        ctx.cancel().await;
+    }
+}
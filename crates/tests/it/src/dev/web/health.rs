@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use serde_json::Value;
+    use serial_test::serial;
+    use tokio::select;
+
+    use lib_core::bus::{MessageBusConfig, DEFAULT_PARTITIONS};
+    use lib_utils::json::value;
+
+    use crate::context::context::{ServiceType, TestContext};
+
+    #[tokio::test]
+    #[serial]
+    async fn ready() {
+        let ctx = TestContext::new(ServiceType::Web).await;
+
+        let checks = ready_checks(&ctx).await;
+        assert!(checks.contains(&"kafka".to_string()));
+        assert!(!checks.contains(&"in_memory_bus".to_string()));
+
+        let response = ctx.get("/health/live").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn ready_on_in_memory_bus() {
+        let ctx = TestContext::with_message_bus(ServiceType::Web, MessageBusConfig::InMemory { partitions: DEFAULT_PARTITIONS }).await;
+
+        // there are no brokers to probe
+        let checks = ready_checks(&ctx).await;
+        assert!(checks.contains(&"in_memory_bus".to_string()));
+        assert!(!checks.contains(&"kafka".to_string()));
+
+        ctx.cancel().await;
+    }
+
+    /// Waits until the web-server is ready, every check is up then. Returns their names.
+    async fn ready_checks(ctx: &TestContext) -> Vec<String> {
+        // tasks are started in the background, so readiness turns green eventually
+        let status = select! {
+            status = wait_ready(ctx) => { status }
+            _ = tokio::time::sleep(Duration::from_secs(10)) => { StatusCode::SERVICE_UNAVAILABLE }
+        };
+        assert_eq!(status, StatusCode::OK);
+
+        let response = ctx.get("/health/ready").await;
+        let report: Value = value(response).await.expect("must be ok");
+        assert_eq!(report["status"], "up");
+        let checks = report["checks"].as_array().expect("must be array");
+        assert!(checks.iter().all(|check| check["status"] == "up"));
+        for dependency in ["postgres", "migrations"] {
+            assert!(checks.iter().any(|check| check["name"] == dependency));
+        }
+        checks.iter()
+            .map(|check| check["name"].as_str().expect("must be str").to_string())
+            .collect()
+    }
+
+    async fn wait_ready(ctx: &TestContext) -> StatusCode {
+        loop {
+            let status = ctx.get("/health/ready").await.status();
+            if status == StatusCode::OK {
+                return status;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
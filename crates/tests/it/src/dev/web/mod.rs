@@ -1,7 +1,9 @@
 use serde_json::{json};
 use tracing::info;
 
+use lib_core::bmc::storage::StorageBmc;
 use lib_core::bmc::user::UserBmc;
+use lib_dto::order::OrderItem;
 use lib_dto::user::{AuthCode, UserForCreate};
 use lib_load::requests::user_context::UserContext;
 
@@ -10,6 +12,12 @@ use crate::context::context::{TestContext};
 mod scenario;
 mod login;
 mod bad_request;
+mod health;
+mod admin;
+mod order;
+mod order_source;
+mod consumer;
+mod book;
 
 /// performs login for further RPC requests
 async fn login(ctx: &mut TestContext, user: &mut UserContext) {
@@ -22,4 +30,10 @@ async fn login(ctx: &mut TestContext, user: &mut UserContext) {
 
     let user_to_create = UserForCreate::new(user.phone(), user.phone(), "John", "Doe");
     let _ = UserBmc::create(ctx.app_context(), user_to_create).await;
-}
\ No newline at end of file
+}
+
+/// stocks every book of `BOOK_LIST`, the books must be added first
+async fn restock(ctx: &TestContext, quantity: i64) {
+    let stock: Vec<OrderItem> = (1..=5).map(|book_id| OrderItem::new(book_id, quantity)).collect();
+    StorageBmc::restock(ctx.app_context(), &stock).await.expect("must be ok");
+}
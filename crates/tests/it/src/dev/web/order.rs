@@ -0,0 +1,403 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+    use serial_test::serial;
+
+    use lib_core::bmc::book_sales::BookSalesBmc;
+    use lib_core::bmc::storage::StorageBmc;
+    use lib_core::bmc::user::UserBmc;
+    use lib_core::task::main_task::TaskManager;
+    use lib_dto::book::BookList;
+    use lib_dto::order::{OrderContent, OrderId, OrderItem, OrderStatus, OrderStored, OrderWatch};
+    use lib_load::requests::user_context::UserContext;
+    use lib_load::scenario::books::BOOK_LIST;
+    use lib_utils::rpc::request;
+
+    use crate::context::context::{ServiceType, TestContext};
+    use crate::dev::web::{login, restock};
+
+    #[tokio::test]
+    #[serial]
+    async fn order_history() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+
+        let delivered = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+                if order.status() == &OrderStatus::Delivered {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await;
+        assert!(delivered.is_ok());
+
+        let history: Value = user.post_rpc("order_history", json!(order_id)).await;
+        let history = history.as_array().expect("must be array");
+        let transitions: Vec<(Value, Value)> = history.iter()
+            .map(|change| (change["from_status"].clone(), change["to_status"].clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (Value::Null, json!("new")),
+                (json!("new"), json!("in_progress")),
+                (json!("in_progress"), json!("ready_to_deliver")),
+                (json!("ready_to_deliver"), json!("delivered")),
+            ],
+            transitions,
+        );
+        let user_stored = UserBmc::get_by_phone(ctx.app_context(), user.phone()).await.expect("must be ok");
+        assert_eq!(history[0]["actor"], format!("user:{}", user_stored.id()));
+        assert_eq!(history[3]["actor"], "DeliveryTask");
+
+        // a delivered order can't be picked up again
+        let response = user.post("/api/rpc", request("pick_up_order", Some(json!(order_id)))).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let mut other = ctx.user(0);
+        login(&mut ctx, &mut other).await;
+        let response = other.post("/api/rpc", request("order_history", Some(json!(order_id)))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn cancel_order() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+        let initial = StorageBmc::get_quantity(ctx.app_context(), 1).await.expect("must be ok");
+
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+
+        // the pipeline races the cancellation, a cancelled order must give its reservation back
+        let response = user.post("/api/rpc", request("cancel_order", Some(json!(order_id)))).await;
+        let expected = match response.status() {
+            StatusCode::OK => OrderStatus::Cancelled,
+            StatusCode::CONFLICT => OrderStatus::Delivered,
+            status => panic!("unexpected status {}", status),
+        };
+
+        let settled = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+                if order.status() == &expected {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await;
+        assert!(settled.is_ok());
+        // gives a queued order the time to reach the pipeline
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+        assert_eq!(&expected, order.status());
+        let current = StorageBmc::get_quantity(ctx.app_context(), 1).await.expect("must be ok");
+        let expected_quantity = match expected {
+            OrderStatus::Cancelled => initial.quantity(),
+            _ => initial.quantity().map(|quantity| quantity - 2),
+        };
+        assert_eq!(expected_quantity, current.quantity());
+        assert_eq!(Some(0), current.reserved());
+
+        // neither a cancelled nor a delivered order can be cancelled
+        let response = user.post("/api/rpc", request("cancel_order", Some(json!(order_id)))).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let mut other = ctx.user(0);
+        login(&mut ctx, &mut other).await;
+        let response = other.post("/api/rpc", request("cancel_order", Some(json!(order_id)))).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn pick_up_order() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+
+        // as if the storage stage had reserved the books, the pipeline doesn't learn about the order
+        let pg_pool = ctx.app_context().pg_pool();
+        let user_stored = UserBmc::get_by_phone(ctx.app_context(), user.phone()).await.expect("must be ok");
+        sqlx::query("ALTER TABLE order_info DISABLE TRIGGER order_notify_insert")
+            .execute(pg_pool).await.expect("must be ok");
+        let order_id: OrderId = sqlx::query_as(
+            "INSERT INTO order_info (user_id, content, status, created_at, updated_at) \
+             VALUES ($1, $2, 'ready_to_deliver', now(), now()) \
+             RETURNING order_id"
+        )
+            .bind(user_stored.id())
+            .bind(sqlx::types::Json(OrderContent::new(vec![OrderItem::new(1, 2)])))
+            .fetch_one(pg_pool).await.expect("must be ok");
+        sqlx::query("ALTER TABLE order_info ENABLE TRIGGER order_notify_insert")
+            .execute(pg_pool).await.expect("must be ok");
+        sqlx::query("UPDATE book_storage SET reserved = reserved + 2 WHERE book_id = 1")
+            .execute(pg_pool).await.expect("must be ok");
+
+        let order: OrderStored = user.post_rpc("pick_up_order", json!(order_id)).await;
+        assert_eq!(&OrderStatus::Delivered, order.status());
+
+        // the reservation is turned into a decrement, as a delivery does
+        let storage = StorageBmc::get_quantity(ctx.app_context(), 1).await.expect("must be ok");
+        assert_eq!(Some(8), storage.quantity());
+        assert_eq!(Some(0), storage.reserved());
+
+        let history: Value = user.post_rpc("order_history", json!(order_id)).await;
+        let last = history.as_array().expect("must be array").last().expect("must be some").clone();
+        assert_eq!(last["to_status"], "delivered");
+        assert_eq!(last["actor"], format!("user:{}", user_stored.id()));
+
+        // queued in the transaction of the pick-up, so the sales projection counts it
+        let event_type: String = sqlx::query_scalar(
+            "SELECT payload->>'event_type' FROM outbox WHERE message_key = $1 ORDER BY id DESC LIMIT 1"
+        )
+            .bind(order_id.order_id().to_string())
+            .fetch_one(pg_pool).await.expect("must be queued");
+        assert_eq!("order_delivered", event_type);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn insufficient_stock() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 3).await;
+
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let delivered: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Delivered, wait_settled(&user, &delivered).await);
+
+        // only one book is left
+        let rejected: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Failed, wait_settled(&user, &rejected).await);
+
+        let history: Value = user.post_rpc("order_history", json!(rejected)).await;
+        let last = history.as_array().expect("must be array").last().expect("must be some").clone();
+        assert_eq!(last["actor"], "StorageTask");
+        assert!(last["reason"].as_str().expect("must be str").contains("Not enough stock of book 1"));
+
+        let storage = StorageBmc::get_quantity(ctx.app_context(), 1).await.expect("must be ok");
+        assert_eq!(Some(1), storage.quantity());
+        assert_eq!(Some(0), storage.reserved());
+
+        // a negative quantity would add to the stock
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, -2)));
+        let response = user.post("/api/rpc", request("create_order", Some(json!(order_content)))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn recover_stale_order() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+
+        // as if the process had stopped after the order was taken into work, nobody is notified
+        let pg_pool = ctx.app_context().pg_pool();
+        let user_stored = UserBmc::get_by_phone(ctx.app_context(), user.phone()).await.expect("must be ok");
+        sqlx::query("ALTER TABLE order_info DISABLE TRIGGER order_notify_insert")
+            .execute(pg_pool).await.expect("must be ok");
+        let order_id: OrderId = sqlx::query_as(
+            "INSERT INTO order_info (user_id, content, status, created_at, updated_at) \
+             VALUES ($1, $2, 'in_progress', now() - interval '1 hour', now() - interval '1 hour') \
+             RETURNING order_id"
+        )
+            .bind(user_stored.id())
+            .bind(sqlx::types::Json(OrderContent::new(vec![OrderItem::new(1, 2)])))
+            .fetch_one(pg_pool).await.expect("must be ok");
+        sqlx::query("ALTER TABLE order_info ENABLE TRIGGER order_notify_insert")
+            .execute(pg_pool).await.expect("must be ok");
+
+        // the sweeper runs every 3 seconds in tests
+        assert_eq!(OrderStatus::Delivered, wait_settled(&user, &order_id).await);
+        let history: Value = user.post_rpc("order_history", json!(order_id)).await;
+        let actors: Vec<&str> = history.as_array().expect("must be array").iter()
+            .map(|change| change["actor"].as_str().expect("must be str"))
+            .collect();
+        assert_eq!(vec!["StorageTask", "DeliveryTask"], actors);
+
+        let storage = StorageBmc::get_quantity(ctx.app_context(), 1).await.expect("must be ok");
+        assert_eq!(Some(8), storage.quantity());
+        assert_eq!(Some(0), storage.reserved());
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn order_updates() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+
+        let statuses = tokio::time::timeout(Duration::from_secs(10), async {
+            let mut statuses = vec![OrderStatus::New];
+            while statuses.last() != Some(&OrderStatus::Delivered) {
+                let order_watch = OrderWatch::new(order_id.order_id(), statuses.last().expect("must be some").clone());
+                let order: OrderStored = user.post_rpc("watch_order", json!(order_watch)).await;
+                statuses.push(order.status().clone());
+            }
+            statuses
+        }).await.expect("must be delivered");
+        // a watch answers with the state the order has when it is loaded, which may be a later one
+        assert!(statuses.is_sorted());
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn large_order() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 1000).await;
+
+        // the content alone is far beyond the 8000 bytes a notification may carry
+        let items = (0..500).map(|i| OrderItem::new(i % 5 + 1, 1)).collect();
+        let order_content = OrderContent::new(items);
+        assert!(serde_json::to_string(&order_content).expect("must be ok").len() > 8000);
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Delivered, wait_settled(&user, &order_id).await);
+
+        let storage = StorageBmc::get_quantity(ctx.app_context(), 1).await.expect("must be ok");
+        assert_eq!(Some(900), storage.quantity());
+
+        ctx.cancel().await;
+    }
+
+        #[tokio::test]
+    #[serial]
+    async fn listener_reconnects() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+
+        // the listener's last statement is its LISTEN
+        let main_tx = ctx.app_context().main_tx();
+        let terminated: Vec<bool> = sqlx::query_scalar(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+             WHERE query LIKE 'LISTEN%' AND pid <> pg_backend_pid()"
+        )
+            .fetch_all(ctx.app_context().pg_pool()).await.expect("must be ok");
+        assert!(!terminated.is_empty());
+
+        // created while the listener may still be away, the catch-up routes it then
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Delivered, wait_settled(&user, &order_id).await);
+
+        let status = TaskManager::listener_status(main_tx).await.expect("must be ok");
+        assert!(status.connected());
+        assert!(status.reconnects() >= 1);
+
+        ctx.cancel().await;
+    }
+
+        #[tokio::test]
+    #[serial]
+    async fn book_sales_projection() {
+        let mut ctx = TestContext::new(ServiceType::Web).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(&ctx, 10).await;
+
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2), OrderItem::new(2, 1), OrderItem::new(1, 1)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Delivered, wait_settled(&user, &order_id).await);
+
+        // the delivered event goes through the outbox and kafka before it is projected
+        let projected = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let sales = BookSalesBmc::get_all(ctx.app_context()).await.expect("must be ok");
+                if !sales.is_empty() {
+                    return sales;
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        }).await.expect("must be projected");
+        let sold: Vec<(i64, i64)> = projected.iter().map(|sales| (sales.book_id(), sales.sold())).collect();
+        assert_eq!(vec![(1, 3), (2, 1)], sold);
+
+        ctx.cancel().await;
+    }
+
+        async fn wait_settled(user: &UserContext, order_id: &OrderId) -> OrderStatus {
+        let settled = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+                if matches!(order.status(), OrderStatus::Delivered | OrderStatus::Failed) {
+                    return order.status().clone();
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await;
+        settled.expect("must settle")
+    }
+}
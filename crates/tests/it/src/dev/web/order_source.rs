@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::StatusCode;
+    use serde_json::{json, Value};
+    use serial_test::serial;
+
+    use lib_core::notify::source::{OrderEvent, OrderEventChannel, OrderSourceConfig};
+    use lib_dto::book::BookList;
+    use lib_dto::order::{OrderContent, OrderId, OrderItem, OrderStatus, OrderStored};
+    use lib_load::requests::user_context::UserContext;
+    use lib_load::scenario::books::BOOK_LIST;
+    use lib_utils::rpc::request;
+
+    use crate::context::context::{ServiceType, TestContext};
+    use crate::dev::web::{login, restock};
+
+    #[tokio::test]
+    #[serial]
+    async fn channel_source() {
+        let (events, channel) = OrderEventChannel::new(8);
+        let mut ctx = TestContext::with_order_source(ServiceType::Web, OrderSourceConfig::Channel(channel)).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+        add_books(&ctx, &user).await;
+
+        // nobody listens to the notifications, the order waits for its event
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+        assert_eq!(&OrderStatus::New, order.status());
+
+        events.send(OrderEvent::Created(order_id.order_id())).await.expect("must be ok");
+        assert_eq!(OrderStatus::Delivered, wait_delivered(&user, &order_id).await);
+
+        ctx.cancel().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn polling_source() {
+        let order_source = OrderSourceConfig::Poll { interval: Duration::from_millis(100) };
+        let mut ctx = TestContext::with_order_source(ServiceType::Web, order_source).await;
+        let mut user = ctx.user(6);
+        login(&mut ctx, &mut user).await;
+        add_books(&ctx, &user).await;
+
+        let order_content = OrderContent::new(vec!(OrderItem::new(1, 2)));
+        let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
+        assert_eq!(OrderStatus::Delivered, wait_delivered(&user, &order_id).await);
+
+        // the poller claims the order by taking it into work
+        let history: Value = user.post_rpc("order_history", json!(order_id)).await;
+        assert_eq!(history[1]["to_status"], "in_progress");
+        assert_eq!(history[1]["actor"], "OrderPoller");
+
+        ctx.cancel().await;
+    }
+
+    async fn add_books(ctx: &TestContext, user: &UserContext) {
+        let book_list: BookList = serde_json::from_str(BOOK_LIST).expect("must be ok");
+        let add_books_response = user.post("/api/rpc", request("add_books", Some(book_list))).await;
+        assert_eq!(add_books_response.status(), StatusCode::OK);
+        restock(ctx, 10).await;
+    }
+
+    async fn wait_delivered(user: &UserContext, order_id: &OrderId) -> OrderStatus {
+        let delivered = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let order: OrderStored = user.post_rpc("check_order", json!(order_id)).await;
+                if order.status() == &OrderStatus::Delivered {
+                    return order.status().clone();
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        }).await;
+        delivered.expect("must be delivered")
+    }
+}
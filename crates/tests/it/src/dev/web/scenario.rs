@@ -3,27 +3,39 @@ mod tests {
     use std::time::Duration;
 
     use axum::http::StatusCode;
-    use rdkafka::consumer::{CommitMode, Consumer};
-    use rdkafka::Message;
     use serde_json::{json, Value};
     use serial_test::serial;
     use tokio::select;
     use tracing::info;
     use tracing::log::error;
     use lib_dto::book::BookList;
+    use lib_dto::event::{OrderEvent, ORDER_EVENT_SCHEMA_VERSION};
     use lib_dto::order::{OrderContent, OrderId, OrderItem, OrderStatus, OrderStored};
     use lib_load::requests::user_context::UserContext;
     use lib_load::scenario::books::BOOK_LIST;
     use lib_utils::rpc::request;
-    use lib_core::task::kafka::consumer_task::create;
+    use lib_core::bmc::outbox::OutboxBmc;
+    use lib_core::bus::{MessageBusConfig, DEFAULT_PARTITIONS};
+    use lib_core::task::kafka::producer_task::KafkaProducerResponse;
+    use lib_core::task::main_task::TaskManager;
 
     use crate::context::context::{ServiceType, TestContext};
-    use crate::dev::web::login;
+    use crate::dev::web::{login, restock};
 
     #[tokio::test]
     #[serial]
     async fn scenario() {
-        let mut ctx = TestContext::new(ServiceType::Web).await;
+        run_scenario(TestContext::new(ServiceType::Web).await).await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn scenario_in_memory() {
+        let message_bus = MessageBusConfig::InMemory { partitions: DEFAULT_PARTITIONS };
+        run_scenario(TestContext::with_message_bus(ServiceType::Web, message_bus).await).await;
+    }
+
+    async fn run_scenario(mut ctx: TestContext) {
         let mut user = ctx.user(6);
         login(&mut ctx, &mut user).await;
 
@@ -35,6 +47,10 @@ mod tests {
         let all_books_request = request("all_books", Some(Value::Null));
         let book_list: BookList = user.post_ok("/api/rpc", all_books_request).await;
         assert_eq!(5, book_list.book_list().len());
+        let book_ids: Vec<i64> = book_list.book_list().iter()
+            .map(|book| book.id.expect("must have an id"))
+            .collect();
+        restock(&ctx, 100).await;
 
         //let description = BookDescription::new("the");
         //let book_list: BookList = user.post_rpc("books_by_description", json!(description)).await;
@@ -45,8 +61,8 @@ mod tests {
         let mut order_ids: Vec<i64> = Vec::with_capacity(iterations);
 
         for i in 1..iterations {
-            let order_item_1 = OrderItem::new(1, 2);
-            let order_item_2 = OrderItem::new(2, 4);
+            let order_item_1 = OrderItem::new(book_ids[0], 2);
+            let order_item_2 = OrderItem::new(book_ids[1], 4);
             let order_content = OrderContent::new(vec!(order_item_1, order_item_2));
             let order_id: OrderId = user.post_rpc("create_order", json!(order_content)).await;
             assert_eq!(i as i64, order_id.order_id());
@@ -69,6 +85,17 @@ mod tests {
 
         assert_eq!(orders.len(), orders_from_kafka.len());
 
+        // rows are marked sent right after the broker acknowledged them
+        let pending = select! {
+            pending = wait_outbox_sent(&ctx) => { pending }
+            _pending = tokio::time::sleep(Duration::from_secs(5)) => { -1 }
+        };
+        assert_eq!(0, pending);
+
+        // nothing is left to relay, the reply only comes once the relay is over
+        let relayed = TaskManager::relay_outbox(ctx.app_context().main_tx()).await.expect("must be ok");
+        assert_eq!(KafkaProducerResponse::Produced(0), relayed);
+
         ctx.cancel().await;
     }
 
@@ -77,7 +104,7 @@ mod tests {
         for order_id in order_ids {
             let check_order_id = OrderId::new(order_id);
             let mut check_stored: OrderStored = user.post_rpc("check_order", json!(check_order_id)).await;
-            while &check_stored.status() != &&OrderStatus::Delivered {
+            while check_stored.status() != &OrderStatus::Delivered {
                 tokio::time::sleep(Duration::from_millis(100)).await;
                 check_stored = user.post_rpc("check_order", json!(check_order_id)).await;
             }
@@ -86,14 +113,20 @@ mod tests {
         orders
     }
 
-    async fn check_kafka(ctx: &TestContext, orders: &Vec<OrderStored>) -> Vec<OrderStored> {
-        let app_config = ctx.app_context().app_config();
-        let consumer = create(app_config.clone(), "test_group").await;
+    async fn wait_outbox_sent(ctx: &TestContext) -> i64 {
+        loop {
+            let pending = OutboxBmc::count_pending(ctx.app_context()).await.expect("must be ok");
+            if pending == 0 {
+                return pending;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
 
-        info!("Handling kafka consumer task");
-        consumer.subscribe(
-            &["order-topic"]
-        ).expect("Can't Subscribe");
+    async fn check_kafka(ctx: &TestContext, orders: &[OrderStored]) -> Vec<OrderStored> {
+        let consumer = ctx.app_context().message_bus()
+            .subscribe(&["order-topic"], "test_group").await
+            .expect("Can't Subscribe");
 
         let mut orders_from_kafka = Vec::with_capacity(orders.len());
 
@@ -101,16 +134,17 @@ mod tests {
             match consumer.recv().await {
                 Err(e) => error!("{:?}",e),
                 Ok(message) => {
-                    match message.payload_view::<str>() {
-                        None => info!("None message"),
-                        Some(Ok(msg)) => {
+                    match message.payload_str() {
+                        Ok(msg) => {
                             info!("Message Consumed in test: {}", &msg);
-                            let order: OrderStored = serde_json::from_str(msg).expect("must be ok");
-                            orders_from_kafka.push(order);
+                            let event: OrderEvent = serde_json::from_str(msg).expect("must be ok");
+                            assert_eq!(ORDER_EVENT_SCHEMA_VERSION, event.schema_version());
+                            assert_eq!(Some(event.order_id().to_string().as_str()), message.key());
+                            orders_from_kafka.push(event.payload().clone());
                         },
-                        Some(Err(e)) => error!("Error Parsing : {}",e)
+                        Err(e) => error!("Error Parsing : {}",e)
                     }
-                    consumer.commit_message(&message, CommitMode::Async).unwrap();
+                    consumer.commit(&message).unwrap();
                 }
             }
         }
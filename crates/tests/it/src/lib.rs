@@ -1,4 +1,6 @@
+#[cfg(test)]
 mod dev;
 
+#[cfg(test)]
 mod context;
 mod utils;
\ No newline at end of file
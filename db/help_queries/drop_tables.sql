@@ -1,4 +1,10 @@
 DROP TABLE IF EXISTS _sqlx_migrations;
+DROP TABLE IF EXISTS outbox;
+DROP TABLE IF EXISTS order_status_history;
+DROP TABLE IF EXISTS failed_order;
+DROP TABLE IF EXISTS book_sales;
+DROP TABLE IF EXISTS projected_order;
+DROP TABLE IF EXISTS consumer_offset;
 DROP TABLE IF EXISTS order_info;
 DROP TABLE IF EXISTS book_storage;
 DROP TABLE IF EXISTS book_info;
@@ -0,0 +1,15 @@
+-- Events written in the same transaction as the order update, relayed to Kafka by KafkaProducerTask
+CREATE TABLE IF NOT EXISTS "outbox" (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  topic varchar(256) NOT NULL,
+  message_key varchar(256) NOT NULL,
+  payload JSONB NOT NULL,
+  attempts INT NOT NULL DEFAULT 0,
+  last_error TEXT,
+  -- also used as a lease while a relay is publishing the row
+  next_attempt_at timestamp with time zone NOT NULL DEFAULT now(),
+  sent_at timestamp with time zone,
+  created_at timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS outbox_pending_idx ON outbox (next_attempt_at) WHERE sent_at IS NULL;
@@ -0,0 +1,20 @@
+-- Orders can now be cancelled or fail. The enum is recreated instead of extended,
+-- ALTER TYPE ... ADD VALUE can't run inside the migration transaction on Postgres 11.
+ALTER TYPE order_status RENAME TO order_status_old;
+CREATE TYPE order_status AS ENUM ('new', 'in_progress', 'ready_to_deliver', 'delivered', 'cancelled', 'failed');
+ALTER TABLE order_info ALTER COLUMN status TYPE order_status USING status::text::order_status;
+DROP TYPE order_status_old;
+
+-- Every status transition of an order, written in the same transaction as the status update
+CREATE TABLE IF NOT EXISTS "order_status_history" (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  order_id BIGINT NOT NULL REFERENCES order_info(order_id) ON DELETE CASCADE,
+  -- NULL for the creation of the order
+  from_status order_status,
+  to_status order_status NOT NULL,
+  actor varchar(128) NOT NULL,
+  reason TEXT,
+  created_at timestamp with time zone NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS order_status_history_order_idx ON order_status_history (order_id);
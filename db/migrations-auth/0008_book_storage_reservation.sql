@@ -0,0 +1,7 @@
+-- Stock taken by orders which are ready but not delivered yet, available stock is quantity - reserved
+ALTER TABLE book_storage ADD COLUMN IF NOT EXISTS reserved BIGINT NOT NULL DEFAULT 0;
+
+-- the previous add/remove bookkeeping could drive the stock negative
+UPDATE book_storage SET quantity = 0 WHERE quantity < 0;
+
+ALTER TABLE book_storage ADD CONSTRAINT book_storage_reserved_check CHECK (reserved >= 0 AND reserved <= quantity);
@@ -0,0 +1,12 @@
+-- Dead letters: orders whose storage or delivery update failed for good, until an admin requeues them
+CREATE TABLE IF NOT EXISTS "failed_order" (
+  id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+  order_id BIGINT NOT NULL REFERENCES order_info(order_id) ON DELETE CASCADE,
+  stage varchar(128) NOT NULL,
+  attempts INT NOT NULL,
+  last_error TEXT NOT NULL,
+  failed_at timestamp with time zone NOT NULL DEFAULT now(),
+  requeued_at timestamp with time zone
+);
+
+CREATE INDEX IF NOT EXISTS failed_order_pending_idx ON failed_order (order_id) WHERE requeued_at IS NULL;
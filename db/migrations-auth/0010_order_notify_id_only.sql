@@ -0,0 +1,19 @@
+-- The order content may exceed the 8000 bytes pg_notify takes, so only the id is sent
+-- and the listener loads the order itself
+CREATE OR REPLACE FUNCTION table_update_notify() RETURNS trigger AS $$
+DECLARE
+  order_id bigint;
+BEGIN
+  IF TG_OP = 'INSERT' OR TG_OP = 'UPDATE' THEN
+    order_id = NEW.order_id;
+  ELSE
+    order_id = OLD.order_id;
+  END IF;
+  PERFORM pg_notify('table_update', json_build_object(
+  'table', TG_TABLE_NAME,
+  'order_id', order_id,
+  'action_type', TG_OP
+  )::text);
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
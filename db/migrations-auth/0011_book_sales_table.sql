@@ -0,0 +1,12 @@
+-- Read model projected from the delivered order events, see BookSalesProjector
+CREATE TABLE IF NOT EXISTS "book_sales" (
+  book_id BIGINT PRIMARY KEY,
+  sold BIGINT NOT NULL DEFAULT 0,
+  updated_at timestamp with time zone NOT NULL DEFAULT now()
+);
+
+-- Orders already counted, events are delivered at least once
+CREATE TABLE IF NOT EXISTS "projected_order" (
+  order_id BIGINT PRIMARY KEY,
+  projected_at timestamp with time zone NOT NULL DEFAULT now()
+);
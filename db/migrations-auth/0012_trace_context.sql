@@ -0,0 +1,5 @@
+-- W3C traceparent of the request which created the order, continued by the pipeline and the consumers
+ALTER TABLE order_info ADD COLUMN IF NOT EXISTS traceparent TEXT;
+
+-- sent as a Kafka header along with the message
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS traceparent TEXT;
@@ -0,0 +1,10 @@
+-- Offsets of the consumers which keep them next to their side effects, see ConsumerOffsets::Postgres
+CREATE TABLE IF NOT EXISTS "consumer_offset" (
+  group_id varchar(256) NOT NULL,
+  topic varchar(256) NOT NULL,
+  partition INT NOT NULL,
+  -- offset of the next message to process
+  next_offset BIGINT NOT NULL,
+  updated_at timestamp with time zone NOT NULL DEFAULT now(),
+  PRIMARY KEY (group_id, topic, partition)
+);
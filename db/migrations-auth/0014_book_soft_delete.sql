@@ -0,0 +1,3 @@
+-- deleted books keep their row, storage and past orders still reference them,
+-- their title and isbn stay taken, importing the isbn again restores the book
+ALTER TABLE book_info ADD COLUMN IF NOT EXISTS deleted_at timestamp with time zone;